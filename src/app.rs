@@ -1,22 +1,34 @@
 use crate::{
+    aseprite::{self, AsepriteFile},
     brush::Brush,
-    compiler::{CompileError, Compiler},
+    camera::Camera,
+    compiler::{CompileError, Compiler, Program},
     coordinate_frame::CoordinateFrames,
     demos::{Demo, DemoSection},
     field::RgbaField,
-    history::SnapshotCause,
+    history::{History, SnapshotCause},
+    image_import::{ImportSummary, UnknownAlpha, import_image},
     interpreter::{Interpreter, StabilizeOutcome},
+    interpreter_worker::InterpreterWorker,
+    lints::{Lint, lint_program},
     material::Material,
     material_effects::material_map_effects,
-    math::{point::Point, rect::Rect, rgba8::Rgba8},
+    math::{pixel::Pixel, point::Point, rect::Rect, rgba8::Rgba8},
     painting::{
         gl_garbage::gl_gc,
         view_painter::{DrawView, ViewPainter},
     },
+    palettes::Palette,
     pixmap::{MaterialMap, Pixmap},
+    png_metadata::PngMetadata,
+    project::{self, Project},
     rule::CanvasInput,
     rule_activity::RuleActivity,
+    rule_stats::{RuleStatsColumn, rule_label, sorted_by},
     run_mode::{RunMode, RunSettings, RunSpeed},
+    share_link,
+    solver::{near_miss::diagnose, plan::SearchLimits},
+    topology::{AtomicTime, MaskedTopology},
     utils::monotonic_time,
     view::{EditMode, View, ViewInput, ViewSettings},
     widgets::{
@@ -29,24 +41,50 @@ use glow::HasContext;
 use itertools::Itertools;
 use log::{info, warn};
 use std::{
+    ffi::OsStr,
     fs,
     path::{Path, PathBuf},
     rc::Rc,
     sync::{Arc, Mutex, mpsc},
 };
-use web_time::Instant;
 
 #[derive(Debug, Clone)]
 pub struct Clipboard {
     pub material_map: MaterialMap,
+
+    /// Image put on the system clipboard
+    pub rgba_field: RgbaField,
 }
 
 impl Clipboard {
     pub fn new(material_map: MaterialMap) -> Self {
-        Self { material_map }
+        let rgba_field = material_map_effects(&material_map, Rgba8::TRANSPARENT);
+        Self {
+            material_map,
+            rgba_field,
+        }
     }
 }
 
+/// A recompiled program with the lints of the world it was compiled from
+type LintedProgram = Result<(Program, Vec<Lint>), Vec<CompileError>>;
+
+/// Lints index the world with region keys of the program, so they have to run on the same world.
+fn recompile_and_lint(compiler: &Compiler, world: &World, previous: &Program) -> LintedProgram {
+    let program = compiler.recompile(world, previous)?;
+    let lints = lint_program(&program, world);
+    Ok((program, lints))
+}
+
+/// Explanation why a rule does not match at a location, drawn over the canvas.
+#[derive(Debug, Clone)]
+pub struct NearMissOverlay {
+    pub message: String,
+
+    /// The failing pattern element first, then the regions the partial morphism maps to
+    pub bounds: Vec<Rect<i64>>,
+}
+
 pub struct TickTimer {
     /// Time elapsed since tick start
     pub tick_elapsed: f64,
@@ -91,14 +129,65 @@ pub struct EguiApp {
     canvas_input: CanvasInput,
 
     file_name: String,
+
+    /// Stored in the metadata of saved pngs, empty if there is none
+    title: String,
+    description: String,
+
+    /// Import settings for images that were not saved by topolang
+    unknown_alpha: UnknownAlpha,
+    import_palette: Option<usize>,
+
+    /// Layer of Aseprite files to load, all visible layers are flattened if empty
+    aseprite_layer: String,
+
+    /// What the last import converted, shown until closed
+    import_summary: Option<String>,
+
     // current_folder: PathBuf,
     compiler: Compiler,
-    compile_error: Option<CompileError>,
+    /// Errors of the last failed compile, shown as overlays on the canvas.
+    compile_errors: Vec<CompileError>,
+
+    /// Lints of the last successfully compiled program
+    lints: Vec<Lint>,
     interpreter: Option<Interpreter>,
+
+    /// Runs a copy of `interpreter` while the run mode is not paused. The changes of each snapshot
+    /// the worker publishes are then applied to `view.world` and edits to it are sent to the
+    /// worker.
+    worker: Option<InterpreterWorker>,
     run_settings: RunSettings,
 
+    /// Modifications of the world up to this time have been checked by `auto_recompile`.
+    compile_checked_time: AtomicTime,
+
+    /// Result of a recompile that is running in the background.
+    pending_compile: Option<mpsc::Receiver<LintedProgram>>,
+
+    /// Run mode before the program was paused because recompiling failed. It is resumed once the
+    /// error is fixed.
+    paused_by_compile_error: Option<RunMode>,
+
+    /// Generic rule whose search exceeded the search limits, running was paused because of it.
+    search_budget_exceeded: Option<usize>,
+
+    /// Applied to the interpreter whenever it is created or paused.
+    search_limits: SearchLimits,
+
     rule_activity: RuleActivity,
 
+    /// Column the rule statistics table is sorted by
+    rule_stats_column: RuleStatsColumn,
+
+    /// Color rule frames by the value of `rule_stats_column`
+    show_rule_heatmap: bool,
+
+    /// Generic rule to diagnose at the next click on the canvas
+    diagnosed_rule: Option<usize>,
+
+    near_miss: Option<NearMissOverlay>,
+
     // stabilize: bool,
     // stabilize_count: i64,
     #[cfg(not(target_arch = "wasm32"))]
@@ -113,6 +202,10 @@ pub struct EguiApp {
     /// have the proper view_rect
     reset_camera_requested: bool,
 
+    /// World rect the camera should fit once the view_rect is known, from the metadata of a
+    /// loaded png. Takes precedence over `reset_camera_requested`.
+    camera_rect_requested: Option<Rect<i64>>,
+
     tick_timer: TickTimer,
 
     #[cfg(feature = "link_ui")]
@@ -133,6 +226,31 @@ impl EguiApp {
         Demo::by_filename(&demo_filename)
     }
 
+    /// World shared with `copy_share_link`, as png
+    #[cfg(target_arch = "wasm32")]
+    fn get_url_world() -> Option<Vec<u8>> {
+        let hash = web_sys::window()?.location().hash().ok()?;
+        share_link::decode_fragment(&hash).unwrap_or_else(|err| {
+            warn!("Ignoring world in link with error {err}");
+            None
+        })
+    }
+
+    /// Url of the web build the app runs in, empty for the native app
+    fn page_url() -> String {
+        #[cfg(target_arch = "wasm32")]
+        {
+            web_sys::window()
+                .and_then(|window| window.location().href().ok())
+                .unwrap_or_default()
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            String::new()
+        }
+    }
+
     pub unsafe fn new(cc: &eframe::CreationContext<'_>) -> Self {
         // let gl = cc.gl.as_ref().map(|arc| arc.as_ref());
         let gl_arc = cc.gl.clone().unwrap();
@@ -167,9 +285,9 @@ impl EguiApp {
         let gl = cc.gl.clone().unwrap();
 
         let view_settings = ViewSettings {
-            locked: false,
             edit_mode: EditMode::Pointer,
             brush: Brush::default(),
+            palette: 0,
         };
 
         let (channel_sender, channel_receiver) = mpsc::sync_channel(1);
@@ -183,6 +301,12 @@ impl EguiApp {
             gl,
             show_full_ui: true,
             file_name: "".to_string(),
+            title: String::new(),
+            description: String::new(),
+            unknown_alpha: UnknownAlpha::Nearest,
+            import_palette: None,
+            aseprite_layer: String::new(),
+            import_summary: None,
             run_settings: demo.autorun,
             view_input: ViewInput::EMPTY,
             canvas_input: CanvasInput::default(),
@@ -194,19 +318,37 @@ impl EguiApp {
                 FileChooser::new(saves_path)
             },
             compiler: Compiler::new(),
-            compile_error: None,
+            compile_errors: Vec::new(),
+            lints: Vec::new(),
             interpreter: None,
+            worker: None,
+            compile_checked_time: -1,
+            pending_compile: None,
+            paused_by_compile_error: None,
+            search_budget_exceeded: None,
+            search_limits: SearchLimits::default(),
             rule_activity,
+            rule_stats_column: RuleStatsColumn::SearchTime,
+            show_rule_heatmap: false,
+            diagnosed_rule: None,
+            near_miss: None,
             clipboard: None,
             channel_sender,
             channel_receiver,
             reset_camera_requested: true,
+            camera_rect_requested: None,
             tick_timer: TickTimer::new(),
             #[cfg(feature = "link_ui")]
             link: "".to_string(),
         };
 
         app.compile();
+
+        #[cfg(target_arch = "wasm32")]
+        if let Some(png) = Self::get_url_world() {
+            app.load_file(&png);
+        }
+
         app
     }
 
@@ -261,23 +403,20 @@ impl EguiApp {
         });
     }
 
-    /// Put `material_map` into system clipboard and local clipboard (for WASM)
+    /// Put `material_map` into system clipboard and local clipboard
+    #[cfg_attr(not(target_arch = "wasm32"), allow(unused_variables))]
     pub fn clipboard_put(&mut self, ctx: &egui::Context, material_map: MaterialMap) {
         let clipboard = Clipboard::new(material_map);
-        let rgba_field = material_map_effects(&clipboard.material_map, Rgba8::TRANSPARENT);
 
-        // TODO: Currently egui does not support pasting images from the system clipboard, see
-        //   https://github.com/emilk/egui/issues/2108
-        //   Until the issue is resolved we copy the image as a base64 png, some image editor
-        //   support pasting these, some don't.
-        // Convert material_map to RgbaField and then to egui Image and put into system clipboard
-        // let egui_image = egui::ColorImage::from_rgba_unmultiplied(
-        //     [rgba_field.width() as usize, rgba_field.height() as usize],
-        //     rgba_field.as_raw(),
-        // );
-        // ctx.copy_image(egui_image);
+        // The web app only pastes text, see https://github.com/emilk/egui/issues/2108, so it
+        // copies the image as a base64 png. Some image editors support pasting these.
+        #[cfg(target_arch = "wasm32")]
+        ctx.copy_text(clipboard.rgba_field.encode_base64_png());
 
-        ctx.copy_text(rgba_field.encode_base64_png());
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Err(err) = crate::system_clipboard::set_image(&clipboard.rgba_field) {
+            warn!("Failed to copy image with error {err}");
+        }
 
         self.clipboard = Some(clipboard);
     }
@@ -294,6 +433,49 @@ impl EguiApp {
         }
     }
 
+    /// Paste an image of any origin through the import, at `world_position` or the center of the
+    /// world. The image we copied last is pasted from the local clipboard, which also keeps
+    /// materials that have no image encoding.
+    pub fn clipboard_paste_image(
+        &mut self,
+        rgba_field: &RgbaField,
+        world_position: Option<Point<i64>>,
+    ) {
+        if let Some(clipboard) = self
+            .clipboard
+            .as_ref()
+            .filter(|clipboard| &clipboard.rgba_field == rgba_field)
+        {
+            let material_map = clipboard.material_map.clone();
+            self.view.clipboard_paste(world_position, material_map);
+            return;
+        }
+
+        match self.import_image(rgba_field) {
+            Ok((material_map, summary)) => {
+                self.view.clipboard_paste(world_position, material_map);
+                self.set_import_summary(summary);
+            }
+            Err(err) => self.import_summary = Some(err.to_string()),
+        }
+    }
+
+    /// Paste the image on the system clipboard, returns false if there is none.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn clipboard_paste_system(&mut self, world_position: Option<Point<i64>>) -> bool {
+        match crate::system_clipboard::get_image() {
+            Ok(Some(rgba_field)) => {
+                self.clipboard_paste_image(&rgba_field, world_position);
+                true
+            }
+            Ok(None) => false,
+            Err(err) => {
+                warn!("Failed to read clipboard with error {err}");
+                false
+            }
+        }
+    }
+
     // pub fn clipboard_paste(&mut self) {
     //     let Some(clipboard) = &self.clipboard else {
     //         return;
@@ -318,11 +500,15 @@ impl EguiApp {
 
                 let paste_icon = egui::include_image!("icons/paste.png");
                 if ui.add(icon_button(paste_icon, Self::ICON_SIZE)).clicked() {
-                    if let Some(clipboard) = &self.clipboard {
+                    #[cfg(not(target_arch = "wasm32"))]
+                    let pasted = self.clipboard_paste_system(None);
+                    #[cfg(target_arch = "wasm32")]
+                    let pasted = false;
+
+                    if let (false, Some(clipboard)) = (pasted, &self.clipboard) {
                         self.view
                             .clipboard_paste(None, clipboard.material_map.clone());
                     }
-                    // TODO: Use ViewportCommand::RequestPaste
                 }
 
                 // Undo/redo ui
@@ -356,6 +542,8 @@ impl EguiApp {
             wasm_bindgen_futures::spawn_local(async move {
                 if let Some(file) = rfd::AsyncFileDialog::new()
                     .add_filter("png", &["png"])
+                    .add_filter("project", &[project::EXTENSION])
+                    .add_filter("aseprite", &[aseprite::EXTENSION])
                     .pick_file()
                     .await
                 {
@@ -374,7 +562,7 @@ impl EguiApp {
                 .material_map()
                 .to_rgba_field(Material::TRANSPARENT);
 
-            match bitmap.to_png() {
+            match bitmap.to_png_with_metadata(&self.png_metadata()) {
                 Ok(file_content) => {
                     wasm_bindgen_futures::spawn_local(async move {
                         if let Some(file) = rfd::AsyncFileDialog::new()
@@ -401,6 +589,8 @@ impl EguiApp {
         if ui.button("Open File").clicked() {
             if let Some(path) = rfd::FileDialog::new()
                 .add_filter("png", &["png"])
+                .add_filter("project", &[project::EXTENSION])
+                .add_filter("aseprite", &[aseprite::EXTENSION])
                 .pick_file()
             {
                 self.load_from_path(path);
@@ -410,6 +600,7 @@ impl EguiApp {
         if ui.button("Save File").clicked() {
             if let Some(path) = rfd::FileDialog::new()
                 .add_filter("png", &["png"])
+                .add_filter("project", &[project::EXTENSION])
                 .save_file()
             {
                 self.save_to_path(path);
@@ -449,6 +640,46 @@ impl EguiApp {
             ui.label(format!("Size: {} x {}", size.x, size.y));
         });
 
+        ui.horizontal(|ui| {
+            ui.label("Title:");
+            ui.text_edit_singleline(&mut self.title);
+        });
+        ui.label("Description:");
+        ui.text_edit_multiline(&mut self.description);
+        if ui
+            .button("Copy share link")
+            .on_hover_text(
+                "Copy a link with the selection, or the whole world if nothing is selected, and \
+                the run settings. The native app only copies the #world=... part, append it to \
+                the address of the web version.",
+            )
+            .clicked()
+        {
+            self.copy_share_link(ui.ctx());
+        }
+
+        ui.separator();
+        ui.label("Import of other images");
+        enum_choice_buttons(ui, Some("Unknown alpha"), &mut self.unknown_alpha);
+        let palettes = Palette::palettes();
+        let selected_palette = self
+            .import_palette
+            .and_then(|i_palette| palettes.get(i_palette))
+            .map_or("None", |palette| palette.name.as_str());
+        egui::ComboBox::from_label("Quantize to palette")
+            .selected_text(selected_palette)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.import_palette, None, "None");
+                for (i_palette, palette) in palettes.iter().enumerate() {
+                    ui.selectable_value(&mut self.import_palette, Some(i_palette), &palette.name);
+                }
+            });
+        ui.horizontal(|ui| {
+            ui.label("Aseprite layer:");
+            ui.text_edit_singleline(&mut self.aseprite_layer)
+                .on_hover_text("Empty to flatten all visible layers");
+        });
+
         ui.horizontal_wrapped(|ui| {
             ui.label("Set size:");
             for size in [16, 32, 64, 128, 256, 512, 1024, 2048] {
@@ -504,63 +735,168 @@ impl EguiApp {
         });
     }
 
+    fn last_modification_time(&self) -> AtomicTime {
+        self.view
+            .world
+            .topology()
+            .last_modification()
+            .map_or(-1, |(mtime, _)| mtime)
+    }
+
     pub fn compile(&mut self) {
         info!("Compiling");
-        let program = self.compiler.compile(&self.view.world);
+        self.pending_compile = None;
+        self.paused_by_compile_error = None;
+        self.search_budget_exceeded = None;
+        self.compile_checked_time = self.last_modification_time();
+
+        let program = match &self.interpreter {
+            Some(interpreter) => self
+                .compiler
+                .recompile(&self.view.world, &interpreter.program),
+            None => self.compiler.compile(&self.view.world),
+        };
         match program {
             Ok(program) => {
                 self.rule_activity = RuleActivity::new(&program.rules);
+                self.lints = lint_program(&program, &self.view.world);
 
-                self.interpreter = Some(Interpreter::new(program));
-                self.compile_error = None;
+                match &mut self.interpreter {
+                    Some(interpreter) => {
+                        if let Some(worker) = &mut self.worker {
+                            worker.set_program(program.clone());
+                        }
+                        interpreter.set_program(program, &self.view.world);
+                    }
+                    None => {
+                        let mut interpreter = Interpreter::new(program);
+                        interpreter.search_limits = self.search_limits;
+                        self.interpreter = Some(interpreter);
+                    }
+                }
+                self.compile_errors.clear();
                 info!("Compiling successful");
             }
-            Err(err) => {
-                let bounds = err.bounds.first();
-                warn!(
-                    "Failed to compile with error {}, bounds: {:?}",
-                    err.message, bounds
-                );
-                self.compile_error = Some(err);
-
-                // todo!("Show errors as a popup over the failed rule.");
+            Err(errors) => {
+                for err in &errors {
+                    warn!(
+                        "Failed to compile with error {}, bounds: {:?}",
+                        err.message,
+                        err.bounds.first()
+                    );
+                }
+                self.compile_errors = errors;
+                self.lints.clear();
                 self.interpreter = None;
             }
         }
     }
 
-    /// Returns true if stabilized
-    pub fn tick(&mut self, max_modifications: usize) {
-        let Some(interpreter) = &mut self.interpreter else {
+    /// Recompile in the background if the world was edited in a way that affects the running
+    /// program and swap in the new program once it is ready. The world state is kept.
+    pub fn auto_recompile(&mut self) {
+        if let Some(receiver) = &self.pending_compile {
+            match receiver.try_recv() {
+                Ok(result) => {
+                    self.pending_compile = None;
+                    self.swap_program(result);
+                }
+                Err(mpsc::TryRecvError::Empty) => return,
+                Err(mpsc::TryRecvError::Disconnected) => self.pending_compile = None,
+            }
+        }
+
+        let Some(interpreter) = &self.interpreter else {
             return;
         };
 
-        let ticked = interpreter.tick(&mut self.view.world, &self.canvas_input, max_modifications);
+        let running =
+            self.run_settings.mode != RunMode::Paused || self.paused_by_compile_error.is_some();
+        if !running {
+            return;
+        }
 
-        if ticked.changed() {
-            self.view.add_snapshot(SnapshotCause::Tick);
+        let outdated = interpreter
+            .program
+            .is_outdated(self.view.world.topology(), self.compile_checked_time);
+        self.compile_checked_time = self.last_modification_time();
+        if !outdated {
+            return;
+        }
+
+        info!("Rules were edited, recompiling");
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let compiler = self.compiler.clone();
+            let world = self.view.world.clone();
+            let previous = interpreter.program.clone();
+            let (sender, receiver) = mpsc::channel();
+            std::thread::spawn(move || {
+                // Receiver is gone if the world was replaced in the meantime.
+                let _ = sender.send(recompile_and_lint(&compiler, &world, &previous));
+            });
+            self.pending_compile = Some(receiver);
+        }
+
+        // No threads on the web
+        #[cfg(target_arch = "wasm32")]
+        {
+            let result = recompile_and_lint(&self.compiler, &self.view.world, &interpreter.program);
+            self.swap_program(result);
         }
     }
 
-    pub fn slowmo(&mut self) {
-        if !self.tick_timer.tick_ending {
-            // Only one rule application per tick
+    /// Replace the program of the running interpreter by the result of `auto_recompile`
+    fn swap_program(&mut self, result: LintedProgram) {
+        let Some(interpreter) = &mut self.interpreter else {
             return;
+        };
+
+        match result {
+            Ok((program, lints)) => {
+                self.rule_activity = RuleActivity::new(&program.rules);
+                self.lints = lints;
+                if let Some(worker) = &mut self.worker {
+                    worker.set_program(program.clone());
+                }
+                interpreter.set_program(program, &self.view.world);
+                self.compile_errors.clear();
+                self.search_budget_exceeded = None;
+                if let Some(mode) = self.paused_by_compile_error.take() {
+                    self.run_settings.mode = mode;
+                }
+                info!("Recompiling successful");
+            }
+            Err(errors) => {
+                warn!("Failed to recompile with {} errors", errors.len());
+                self.compile_errors = errors;
+                self.lints.clear();
+                if self.run_settings.mode != RunMode::Paused {
+                    self.stop_worker();
+                    self.view.add_snapshot(SnapshotCause::Run);
+                    self.paused_by_compile_error = Some(self.run_settings.mode);
+                    self.run_settings.mode = RunMode::Paused;
+                }
+            }
         }
+    }
 
+    /// Returns true if stabilized
+    pub fn tick(&mut self, max_modifications: usize) {
         let Some(interpreter) = &mut self.interpreter else {
             return;
         };
 
-        let (outcome, applications) =
-            interpreter.stabilize(&mut self.view.world, &self.canvas_input, 1);
+        let ticked = interpreter.tick(&mut self.view.world, &self.canvas_input, max_modifications);
 
-        for &application in &applications {
-            self.rule_activity.rule_applied(application);
+        if ticked.changed() {
+            self.view.add_snapshot(SnapshotCause::Tick);
         }
 
-        if outcome == StabilizeOutcome::Stable {
-            interpreter.wake_up(&mut self.view.world);
+        if let StabilizeOutcome::SearchBudgetExceeded(i_rule) = ticked.stabilize_outcome {
+            self.search_budget_exceeded = Some(i_rule);
+            self.run_settings.mode = RunMode::Paused;
         }
     }
 
@@ -596,34 +932,81 @@ impl EguiApp {
         Some(String::from_utf8(bytes).unwrap())
     }
 
-    pub fn run(&mut self) {
-        // Check if mouse is pressed on a link
-        if let Some(link) = self.pressed_link() {
-            println!("Link pressed {link}");
+    /// Start or stop the interpreter worker depending on the run mode and exchange edits, input
+    /// and snapshots with it.
+    fn update_worker(&mut self) {
+        let running = self.interpreter.is_some() && self.run_settings.mode != RunMode::Paused;
+        if !running {
+            self.stop_worker();
+            return;
         }
 
-        let Some(interpreter) = &mut self.interpreter else {
+        let Some(worker) = &mut self.worker else {
+            let interpreter = self.interpreter.clone().unwrap();
+            let world = self.view.world.snapshot();
+            self.worker = Some(InterpreterWorker::spawn(
+                world,
+                interpreter,
+                self.run_settings,
+            ));
+            self.view.world.record_edits();
             return;
         };
 
-        // Run for 0.75 * frame_dt but don't wake up regions
-        let now = Instant::now();
-        while now.elapsed().as_secs_f64() < 0.75 * self.tick_timer.dt {
-            let max_modifications = 32;
-            let (outcome, applications) =
-                interpreter.stabilize(&mut self.view.world, &self.canvas_input, max_modifications);
+        if self.view.world.is_recording_edits() {
+            worker.draw(self.view.world.take_edits());
+        } else {
+            // The world was replaced, for example by undo
+            worker.set_world(self.view.world.snapshot());
+            self.view.world.record_edits();
+        }
+        worker.set_input(&self.canvas_input);
+        worker.set_run_settings(self.run_settings);
 
-            for &application in &applications {
-                self.rule_activity.rule_applied(application);
-            }
+        let Some(snapshot) = worker.poll(self.tick_timer.dt) else {
+            return;
+        };
 
-            if outcome == StabilizeOutcome::Stable {
-                if self.tick_timer.tick_ending {
-                    interpreter.wake_up(&mut self.view.world);
-                }
+        for &application in &snapshot.applications {
+            self.rule_activity.rule_applied(application);
+        }
 
-                break;
-            }
+        if let (Some(interpreter), Some(stats)) = (&mut self.interpreter, snapshot.stats) {
+            interpreter.stats = stats;
+        }
+
+        self.view
+            .world
+            .draw_unrecorded(snapshot.changes.into_iter());
+
+        if let Some(i_rule) = snapshot.search_budget_exceeded {
+            warn!("Search exceeded its budget, pausing");
+            self.search_budget_exceeded = Some(i_rule);
+            self.run_settings.mode = RunMode::Paused;
+            self.stop_worker();
+            self.view.add_snapshot(SnapshotCause::Run);
+        }
+    }
+
+    /// Stop the interpreter worker and continue with its world and interpreter.
+    fn stop_worker(&mut self) {
+        let Some(mut worker) = self.worker.take() else {
+            return;
+        };
+
+        if self.view.world.is_recording_edits() {
+            worker.draw(self.view.world.take_edits());
+        } else {
+            worker.set_world(self.view.world.snapshot());
+        }
+
+        let (mut world, interpreter) = worker.stop();
+        world.inherit_rgba_changes(&self.view.world);
+        self.view.world = world;
+
+        // Otherwise compiling failed in the meantime
+        if self.interpreter.is_some() {
+            self.interpreter = Some(interpreter);
         }
     }
 
@@ -647,6 +1030,7 @@ impl EguiApp {
             .selected(self.run_settings.mode == RunMode::Paused);
         if ui.add(pause_button).clicked() {
             if self.run_settings.mode != RunMode::Paused {
+                self.stop_worker();
                 self.view.add_snapshot(SnapshotCause::Run);
                 self.run_settings.mode = RunMode::Paused;
             }
@@ -688,10 +1072,13 @@ impl EguiApp {
             self.run_settings.mode = RunMode::Paused;
         }
 
-        if self.run_settings.mode == RunMode::Slowmo {
-            self.slowmo();
-        } else if self.run_settings.mode == RunMode::Run {
-            self.run();
+        // Check if mouse is pressed on a link
+        let link = match self.run_settings.mode {
+            RunMode::Run => self.pressed_link(),
+            _ => None,
+        };
+        if let Some(link) = link {
+            println!("Link pressed {link}");
         }
     }
 
@@ -807,7 +1194,21 @@ impl EguiApp {
         self.tool_buttons_ui(ui);
         ui.separator();
 
-        brush_chooser(ui, &mut self.view_settings.brush);
+        brush_chooser(
+            ui,
+            &mut self.view_settings.brush,
+            &mut self.view_settings.palette,
+        );
+        ui.separator();
+
+        egui::CollapsingHeader::new("Rule statistics").show(ui, |ui| {
+            self.rule_stats_ui(ui);
+        });
+        ui.separator();
+
+        egui::CollapsingHeader::new("Search limits").show(ui, |ui| {
+            self.search_limits_ui(ui);
+        });
         ui.separator();
 
         // ui.label("History");
@@ -881,39 +1282,199 @@ impl EguiApp {
 
     fn set_world(&mut self, world: World) {
         self.interpreter = None;
+        self.worker = None;
+        self.pending_compile = None;
+        self.paused_by_compile_error = None;
+        self.search_budget_exceeded = None;
+        self.diagnosed_rule = None;
+        self.near_miss = None;
+        self.lints.clear();
         self.rule_activity = RuleActivity::new(&[]);
         self.view = View::new(world);
         self.reset_camera_requested = true;
+        self.camera_rect_requested = None;
+        self.title.clear();
+        self.description.clear();
+        self.import_summary = None;
     }
 
     fn load_file(&mut self, content: &[u8]) {
         info!("Loading a file!");
-        let Ok(rgba_field) = RgbaField::load_from_memory(content) else {
-            warn!("Failed to load png file!");
+        if Project::is_project(content) {
+            let project = str::from_utf8(content)
+                .map_err(anyhow::Error::from)
+                .and_then(Project::decode);
+            match project {
+                Ok(project) => self.set_project(project),
+                Err(err) => warn!("Failed to load project with error {err}"),
+            }
             return;
+        }
+
+        let (rgba_field, metadata) = if AsepriteFile::is_aseprite(content) {
+            let layer_name =
+                (!self.aseprite_layer.is_empty()).then_some(self.aseprite_layer.as_str());
+            let rgba_field = AsepriteFile::decode(content)
+                .and_then(|aseprite_file| aseprite_file.flatten(layer_name));
+            match rgba_field {
+                Ok(rgba_field) => (rgba_field, PngMetadata::default()),
+                Err(err) => {
+                    warn!("Failed to load aseprite file with error {err}");
+                    self.import_summary = Some(err.to_string());
+                    return;
+                }
+            }
+        } else {
+            match RgbaField::load_with_metadata(content) {
+                Ok(loaded) => loaded,
+                Err(err) => {
+                    warn!("Failed to load png file with error {err}");
+                    return;
+                }
+            }
+        };
+        let (material_map, summary) = match self.import_image(&rgba_field) {
+            Ok(imported) => imported,
+            Err(err) => {
+                warn!("Failed to import image with error {err}");
+                self.import_summary = Some(err.to_string());
+                return;
+            }
         };
-        let material_map = MaterialMap::from(rgba_field);
         let world = World::from(material_map);
         self.set_world(world);
+        self.set_png_metadata(metadata);
+        self.set_import_summary(summary);
+    }
+
+    /// Convert an image of any origin with the import settings
+    fn import_image(&self, rgba_field: &RgbaField) -> anyhow::Result<(MaterialMap, ImportSummary)> {
+        let palette = self
+            .import_palette
+            .and_then(|i_palette| Palette::palettes().get(i_palette));
+        import_image(rgba_field, self.unknown_alpha, palette)
+    }
+
+    /// Only show the summary if the import changed anything
+    fn set_import_summary(&mut self, summary: ImportSummary) {
+        self.import_summary = (!summary.is_lossless()).then(|| summary.message());
+    }
+
+    /// Start the loaded png like a demo with the run settings and camera stored in it
+    fn set_png_metadata(&mut self, metadata: PngMetadata) {
+        self.camera_rect_requested = metadata.camera_rect;
+        self.title = metadata.title.unwrap_or_default();
+        self.description = metadata.description.unwrap_or_default();
+
+        if let Some(run_settings) = metadata.run_settings {
+            self.run_settings = run_settings;
+            if self.run_settings.mode != RunMode::Paused {
+                self.compile();
+            }
+        }
+    }
+
+    /// Metadata saved with pngs, the camera rect is the part of the world currently visible.
+    fn png_metadata(&self) -> PngMetadata {
+        let camera = self.view.camera;
+        let view_size = self.view_input.frames.viewport.size();
+        let camera_rect = Rect::low_size(camera.offset, view_size * camera.scale);
+        let non_empty = |text: &String| (!text.is_empty()).then(|| text.clone());
+        PngMetadata {
+            run_settings: Some(self.run_settings),
+            camera_rect: Some(camera_rect.as_i64()),
+            title: non_empty(&self.title),
+            description: non_empty(&self.description),
+        }
+    }
+
+    /// Put a link with the selection or the whole world embedded into the clipboard. The camera
+    /// rect is only kept for the whole world, it would be off for a selection.
+    fn copy_share_link(&mut self, ctx: &egui::Context) {
+        let (material_map, metadata) = match self.view.clipboard_copy() {
+            Some(material_map) => {
+                let metadata = PngMetadata {
+                    camera_rect: None,
+                    ..self.png_metadata()
+                };
+                (material_map, metadata)
+            }
+            None => (self.view.world.material_map().clone(), self.png_metadata()),
+        };
+        let rgba_field = material_map_effects(&material_map, Rgba8::TRANSPARENT);
+        match share_link::encode_fragment(&rgba_field, &metadata) {
+            Ok(fragment) => ctx.copy_text(share_link::share_link(&Self::page_url(), &fragment)),
+            Err(err) => warn!("Failed to create share link with error {err}"),
+        }
     }
 
     fn load_from_path(&mut self, path: impl AsRef<Path>) {
         warn!("Loading from path {:?}", path.as_ref().to_str());
-        let content = match fs::read(path) {
+        let content = match fs::read(&path) {
             Ok(content) => content,
             Err(err) => {
                 warn!("Failed to load file with error {}", err);
                 return;
             }
         };
+
+        // Imports are resolved relative to the loaded file
+        if let Some(folder) = path.as_ref().parent() {
+            self.compiler.set_library_root(folder);
+        }
+
         self.load_file(&content);
     }
 
+    /// Replace the world and restore the editor state saved with it
+    fn set_project(&mut self, project: Project) {
+        self.set_world(World::from(project.material_map));
+        self.view.camera = project.camera;
+        self.reset_camera_requested = false;
+        self.view.grid_size = project.grid_size;
+        if let Some(history) = project.history {
+            self.view.history = History::from_saved(history);
+        }
+        if let Some(palette) = Palette::palettes()
+            .iter()
+            .position(|palette| Some(&palette.name) == project.palette.as_ref())
+        {
+            self.view_settings.palette = palette;
+        }
+
+        self.run_settings = project.run_settings;
+        if self.run_settings.mode != RunMode::Paused {
+            self.compile();
+        }
+    }
+
+    /// The world together with the editor state
+    fn project(&self) -> Project {
+        Project {
+            material_map: self.view.world.material_map().clone(),
+            run_settings: self.run_settings,
+            camera: self.view.camera,
+            grid_size: self.view.grid_size,
+            palette: Palette::palettes()
+                .get(self.view_settings.palette)
+                .map(|palette| palette.name.clone()),
+            history: Some(self.view.history.to_saved()),
+        }
+    }
+
+    /// Saves a project if the extension is `project::EXTENSION`, otherwise a png.
     fn save_to_path(&mut self, path: impl AsRef<Path>) {
         warn!("Saving to path {:?}", path.as_ref().to_str());
+        if path.as_ref().extension() == Some(OsStr::new(project::EXTENSION)) {
+            if let Err(err) = self.project().save(path) {
+                warn!("Failed to save project with error {err}");
+            }
+            return;
+        }
+
         let material_map = self.view.world.material_map();
         let rgba_filed = material_map_effects(material_map, Rgba8::TRANSPARENT);
-        if let Err(err) = rgba_filed.save_png(path) {
+        if let Err(err) = rgba_filed.save_png(path, &self.png_metadata()) {
             warn!("Failed to save with error {err}");
         }
     }
@@ -932,55 +1493,6 @@ impl EguiApp {
         }
         let frames = CoordinateFrames::new(window_size, viewport);
 
-        // Show compile error
-        if let Some(err) = &self.compile_error {
-            // egui::Tooltip::for_widget(&response).show(|ui| {
-            //     ui.label(&err.message);
-            // });
-
-            // egui::Area::new("error_popup".into()).anchor(egui::Align2::LEFT_TOP, [10.0, 10.0]).show(|ui| {
-            //     ui.label(&err.message);
-            // });
-
-            let window_error_pos = if let Some(bounds) = err.bounds.first() {
-                let world_error_pos = bounds.bottom_left().as_f64();
-                let view_pos = self.view.camera.view_from_world() * world_error_pos;
-                let window_pos = frames.window_from_view() * view_pos;
-                window_pos
-            } else {
-                viewport.top_left()
-            };
-
-            let mut open = true;
-            egui::Window::new("Error")
-                .frame(
-                    egui::Frame::default()
-                        .fill(egui::Color32::LIGHT_RED)
-                        .corner_radius(3)
-                        .inner_margin(3),
-                )
-                .title_bar(false)
-                .fixed_pos(window_error_pos)
-                .resizable(false)
-                .collapsible(false)
-                .show(ui.ctx(), |ui| {
-                    ui.horizontal(|ui| {
-                        ui.label(&err.message);
-
-                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                            let close_button = egui::Button::new("❌");
-                            if ui.add(close_button).clicked() {
-                                open = false;
-                            }
-                        });
-                    });
-                });
-
-            if !open {
-                self.compile_error = None;
-            }
-        }
-
         // `ctx.pointer_interact_pos()` is None if mouse is outside the window
         if let Some(egui_mouse) = ui.ctx().pointer_interact_pos() {
             let window_mouse = Point::new(egui_mouse.x as f64, egui_mouse.y as f64);
@@ -1040,6 +1552,18 @@ impl EguiApp {
             }
         }
 
+        if let (Some(i_rule), true) = (self.diagnosed_rule, self.canvas_input.left_mouse_click) {
+            self.diagnosed_rule = None;
+            self.near_miss = self.diagnose_near_miss(i_rule, self.canvas_input.mouse_position);
+        }
+
+        if let Some(camera_rect) = self.camera_rect_requested.take() {
+            let view_rect = Rect::low_size(Point::ZERO, frames.viewport.size());
+            self.view.camera =
+                Camera::fit_world_into_view(camera_rect.cwise_as(), view_rect).round();
+            self.reset_camera_requested = false;
+        }
+
         // Reset camera requested, for example from loading World in Self::new
         if self.reset_camera_requested {
             let view_rect = Rect::low_size(Point::ZERO, frames.viewport.size());
@@ -1114,6 +1638,377 @@ impl EguiApp {
             callback: Arc::new(cb),
         };
         ui.painter().add(callback);
+
+        if self.show_rule_heatmap {
+            self.rule_heatmap_ui(ui, frames);
+        }
+        self.diagnostics_ui(ui, frames);
+    }
+
+    /// Outline and label each diagnostic on the canvas. Clicking a label focuses the camera on
+    /// the diagnostic. Returns the index of the diagnostic that was closed.
+    fn diagnostics_overlay_ui<'a>(
+        &mut self,
+        ui: &mut egui::Ui,
+        frames: CoordinateFrames,
+        id: &str,
+        diagnostics: impl Iterator<Item = (String, &'a [Rect<i64>])>,
+        fill: egui::Color32,
+        stroke_color: egui::Color32,
+    ) -> Option<usize> {
+        let window_from_world = frames.window_from_view() * self.view.camera.view_from_world();
+
+        let mut focus = None;
+        let mut closed = None;
+        for (i, (message, all_bounds)) in diagnostics.enumerate() {
+            for bounds in all_bounds {
+                let bounds = bounds.cwise_as::<f64>();
+                let window_rect = Rect::point(window_from_world * bounds.low())
+                    .bounds_with(window_from_world * bounds.high());
+                ui.painter().rect_stroke(
+                    window_rect.into(),
+                    0,
+                    egui::Stroke::new(2.0, stroke_color),
+                    egui::StrokeKind::Outside,
+                );
+            }
+
+            // Diagnostics without bounds are stacked at the top left of the canvas
+            let label_pos = match all_bounds.first() {
+                Some(bounds) => window_from_world * bounds.bottom_left().as_f64(),
+                None => frames.viewport.top_left() + Point(0.0, 30.0 * i as f64),
+            };
+
+            egui::Area::new(egui::Id::new((id, i)))
+                .fixed_pos(label_pos)
+                .show(ui.ctx(), |ui| {
+                    egui::Frame::default()
+                        .fill(fill)
+                        .stroke(egui::Stroke::new(1.0, stroke_color))
+                        .corner_radius(3)
+                        .inner_margin(3)
+                        .show(ui, |ui| {
+                            ui.horizontal(|ui| {
+                                let label = egui::Label::new(message).sense(egui::Sense::click());
+                                let response = ui.add(label).on_hover_text("Click to focus");
+                                if response.clicked() {
+                                    focus = all_bounds.first().copied();
+                                }
+
+                                if ui.add(egui::Button::new("❌").small()).clicked() {
+                                    closed = Some(i);
+                                }
+                            });
+                        });
+                });
+        }
+
+        if let Some(bounds) = focus {
+            let view_rect = Rect::low_size(Point::ZERO, frames.viewport.size());
+            self.view.focus_camera(bounds, view_rect);
+        }
+
+        closed
+    }
+
+    /// Table of the profiling counters of each rule, clicking a column header sorts by that
+    /// column and clicking a rule focuses the camera on it.
+    pub fn rule_stats_ui(&mut self, ui: &mut egui::Ui) {
+        let Some(interpreter) = &mut self.interpreter else {
+            ui.label("Not compiled");
+            return;
+        };
+
+        ui.horizontal(|ui| {
+            if ui.button("Reset").clicked() {
+                interpreter.reset_stats();
+                if let Some(worker) = &self.worker {
+                    worker.reset_stats();
+                }
+            }
+            ui.checkbox(&mut self.show_rule_heatmap, "Heatmap");
+        });
+
+        let stats = interpreter.generic_rule_stats();
+        let mut focus = None;
+        egui::ScrollArea::both().max_height(300.0).show(ui, |ui| {
+            egui::Grid::new("rule_stats").striped(true).show(ui, |ui| {
+                ui.label("Rule");
+                for column in RuleStatsColumn::ALL {
+                    let header = egui::Button::new(column.as_str())
+                        .selected(column == self.rule_stats_column);
+                    if ui.add(header).clicked() {
+                        self.rule_stats_column = column;
+                    }
+                }
+                ui.end_row();
+
+                for i in sorted_by(&stats, self.rule_stats_column) {
+                    let rule = &interpreter.program.rules[i];
+                    ui.horizontal(|ui| {
+                        if ui.link(rule_label(rule)).clicked() {
+                            focus = rule
+                                .source
+                                .as_ref()
+                                .filter(|source| source.library.is_none())
+                                .map(|source| source.bounds);
+                        }
+
+                        let diagnose = egui::Button::new("?")
+                            .small()
+                            .selected(self.diagnosed_rule == Some(i));
+                        if ui
+                            .add(diagnose)
+                            .on_hover_text("Explain why the rule does not match, click a region")
+                            .clicked()
+                        {
+                            self.diagnosed_rule = Some(i);
+                            self.view_settings.edit_mode = EditMode::Pointer;
+                        }
+                    });
+                    for column in RuleStatsColumn::ALL {
+                        ui.label(column.format(&stats[i]));
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+
+        if let Some(bounds) = focus {
+            let view_rect = Rect::low_size(Point::ZERO, self.view_input.frames.viewport.size());
+            self.view.focus_camera(bounds, view_rect);
+        }
+    }
+
+    /// Budget of a single search and of a whole tick. Only editable while paused, the worker
+    /// owns the interpreter otherwise.
+    fn search_limits_ui(&mut self, ui: &mut egui::Ui) {
+        let limits = &mut self.search_limits;
+        let changed = ui
+            .add_enabled_ui(self.worker.is_none(), |ui| {
+                egui::Grid::new("search_limits")
+                    .show(ui, |ui| {
+                        ui.label("Guesses");
+                        let guesses = ui.add(
+                            egui::DragValue::new(&mut limits.max_guesses)
+                                .range(1..=u64::MAX)
+                                .speed(10_000),
+                        );
+                        ui.end_row();
+
+                        ui.label("Search time");
+                        let search_time = ui.add(
+                            egui::DragValue::new(&mut limits.max_search_time)
+                                .range(0.01..=f64::INFINITY)
+                                .speed(0.1)
+                                .suffix(" s"),
+                        );
+                        ui.end_row();
+
+                        ui.label("Tick time");
+                        let tick_time = ui.add(
+                            egui::DragValue::new(&mut limits.max_tick_time)
+                                .range(0.01..=f64::INFINITY)
+                                .speed(0.1)
+                                .suffix(" s"),
+                        );
+                        ui.end_row();
+
+                        guesses.changed() || search_time.changed() || tick_time.changed()
+                    })
+                    .inner
+            })
+            .inner;
+
+        if let (true, Some(interpreter)) = (changed, &mut self.interpreter) {
+            interpreter.search_limits = self.search_limits;
+        }
+    }
+
+    /// Search rule `i_rule` around the region at `pixel` and explain the deepest partial match.
+    fn diagnose_near_miss(&self, i_rule: usize, pixel: Pixel) -> Option<NearMissOverlay> {
+        let interpreter = self.interpreter.as_ref()?;
+        let topology = self.view.world.topology();
+        let phi_region_key = topology.region_key_at(pixel)?;
+
+        let codom = MaskedTopology::new(topology, &interpreter.program.source);
+        if codom.is_hidden(phi_region_key) {
+            return Some(NearMissOverlay {
+                message: "Rules never match regions of rules".to_string(),
+                bounds: vec![topology[phi_region_key].bounds()],
+            });
+        }
+
+        let budget = self.search_limits.budget(f64::INFINITY);
+        let (near_miss, pattern) = interpreter
+            .program
+            .rules
+            .get(i_rule)?
+            .instances
+            .iter()
+            .filter_map(|instance| {
+                let pattern = &instance.rule.before;
+                let near_miss = diagnose(
+                    &pattern.search_strategy,
+                    &pattern.topology,
+                    &codom,
+                    phi_region_key,
+                    &budget,
+                )?;
+                Some((near_miss, pattern))
+            })
+            .reduce(|deepest, other| {
+                if other.0.is_deeper_than(&deepest.0) {
+                    other
+                } else {
+                    deepest
+                }
+            })?;
+
+        info!("Near miss of rule {i_rule}: {near_miss:?}");
+        let bounds = near_miss
+            .element_bounds(&pattern.topology)
+            .into_iter()
+            .chain(near_miss.mapped_bounds(topology))
+            .collect();
+        Some(NearMissOverlay {
+            message: near_miss.message(),
+            bounds,
+        })
+    }
+
+    /// Fill the frame of each rule with red, the more intense the higher its value in the sorted
+    /// column of the statistics table.
+    fn rule_heatmap_ui(&self, ui: &mut egui::Ui, frames: CoordinateFrames) {
+        let Some(interpreter) = &self.interpreter else {
+            return;
+        };
+
+        let stats = interpreter.generic_rule_stats();
+        let values: Vec<f64> = stats
+            .iter()
+            .map(|stats| self.rule_stats_column.value(stats))
+            .collect();
+        let max_value = values.iter().copied().fold(0.0, f64::max);
+        if max_value <= 0.0 {
+            return;
+        }
+
+        let window_from_world = frames.window_from_view() * self.view.camera.view_from_world();
+        for (rule, value) in interpreter.program.rules.iter().zip(values) {
+            let Some(source) = &rule.source else {
+                continue;
+            };
+            if source.library.is_some() {
+                continue;
+            }
+
+            let bounds = source.bounds.cwise_as::<f64>();
+            let window_rect = Rect::point(window_from_world * bounds.low())
+                .bounds_with(window_from_world * bounds.high());
+            let alpha = (160.0 * value / max_value) as u8;
+            ui.painter().rect_filled(
+                window_rect.into(),
+                0,
+                egui::Color32::from_rgba_unmultiplied(0xFF, 0x00, 0x00, alpha),
+            );
+        }
+    }
+
+    /// Compile errors, lints, exceeded search budgets and near misses drawn over the canvas
+    fn diagnostics_ui(&mut self, ui: &mut egui::Ui, frames: CoordinateFrames) {
+        let paused = if self.paused_by_compile_error.is_some() {
+            " (paused)"
+        } else {
+            ""
+        };
+        let compile_errors = self.compile_errors.clone();
+        let closed = self.diagnostics_overlay_ui(
+            ui,
+            frames,
+            "compile_error",
+            compile_errors
+                .iter()
+                .map(|err| (format!("{}{paused}", err.message), err.bounds.as_slice())),
+            egui::Color32::LIGHT_RED,
+            egui::Color32::from_rgb(0xE0, 0x20, 0x20),
+        );
+        if let Some(i) = closed {
+            self.compile_errors.remove(i);
+        }
+
+        let lints = self.lints.clone();
+        let closed = self.diagnostics_overlay_ui(
+            ui,
+            frames,
+            "lint",
+            lints.iter().map(|lint| {
+                let message = format!("{}: {}", lint.kind.as_str(), lint.message);
+                (message, lint.bounds.as_slice())
+            }),
+            egui::Color32::LIGHT_YELLOW,
+            egui::Color32::from_rgb(0xE0, 0xA0, 0x00),
+        );
+        if let Some(i) = closed {
+            self.lints.remove(i);
+        }
+
+        if let Some(near_miss) = self.near_miss.clone() {
+            let closed = self.diagnostics_overlay_ui(
+                ui,
+                frames,
+                "near_miss",
+                std::iter::once((near_miss.message, near_miss.bounds.as_slice())),
+                egui::Color32::LIGHT_BLUE,
+                egui::Color32::from_rgb(0x20, 0x60, 0xE0),
+            );
+            if closed.is_some() {
+                self.near_miss = None;
+            }
+        }
+
+        if let Some(import_summary) = self.import_summary.clone() {
+            let closed = self.diagnostics_overlay_ui(
+                ui,
+                frames,
+                "import_summary",
+                std::iter::once((import_summary, [].as_slice())),
+                egui::Color32::LIGHT_GREEN,
+                egui::Color32::from_rgb(0x20, 0xA0, 0x40),
+            );
+            if closed.is_some() {
+                self.import_summary = None;
+            }
+        }
+
+        let Some(rule) = self
+            .search_budget_exceeded
+            .zip(self.interpreter.as_ref())
+            .and_then(|(i_rule, interpreter)| interpreter.program.rules.get(i_rule))
+        else {
+            return;
+        };
+        let message = format!(
+            "Search of {} exceeded its budget (paused)",
+            rule_label(rule)
+        );
+        let bounds: Vec<_> = rule
+            .source
+            .iter()
+            .filter(|source| source.library.is_none())
+            .map(|source| source.bounds)
+            .collect();
+        let closed = self.diagnostics_overlay_ui(
+            ui,
+            frames,
+            "search_budget",
+            std::iter::once((message, bounds.as_slice())),
+            egui::Color32::LIGHT_RED,
+            egui::Color32::from_rgb(0xE0, 0x20, 0x20),
+        );
+        if closed.is_some() {
+            self.search_budget_exceeded = None;
+        }
     }
 
     fn full_ui(&mut self, ctx: &egui::Context) {
@@ -1248,12 +2143,12 @@ impl eframe::App for EguiApp {
         let events = ctx.input(|input| input.events.clone());
         for event in events {
             match event {
+                // The native app pastes on key release, see below
+                #[cfg(target_arch = "wasm32")]
                 egui::Event::Paste(paste) => {
                     if let Ok(rgba_field) = RgbaField::decode_base64_png(&paste) {
-                        let material_map = MaterialMap::from(rgba_field);
                         let world_position = self.view_input.world_mouse.as_i64();
-                        self.view
-                            .clipboard_paste(Some(world_position), material_map);
+                        self.clipboard_paste_image(&rgba_field, Some(world_position));
                     }
                 }
                 egui::Event::Copy => {
@@ -1266,6 +2161,16 @@ impl eframe::App for EguiApp {
             }
         }
 
+        // egui-winit swallows the paste shortcut and only sends a paste event if the clipboard
+        // contains text, but the release of the key still arrives.
+        #[cfg(not(target_arch = "wasm32"))]
+        if !ctx.wants_keyboard_input()
+            && ctx.input(|input| input.modifiers.command && input.key_released(egui::Key::V))
+        {
+            let world_position = self.view_input.world_mouse.as_i64();
+            self.clipboard_paste_system(Some(world_position));
+        }
+
         ctx.input(|input| {
             if let Some(file) = input.raw.dropped_files.last().cloned() {
                 warn!(
@@ -1282,6 +2187,8 @@ impl eframe::App for EguiApp {
         // visual.window_shadow = epaint::Shadow::NONE;
         ctx.set_visuals(visual);
 
+        self.auto_recompile();
+
         let compact_ui = Self::screen_is_narrow(ctx) || !self.show_full_ui;
         if compact_ui {
             self.compact_ui(ctx);
@@ -1293,11 +2200,11 @@ impl eframe::App for EguiApp {
             self.central_panel(ui);
         });
 
-        self.view_settings.locked =
-            [RunMode::Slowmo, RunMode::Run].contains(&self.run_settings.mode);
         self.view
             .handle_input(&mut self.view_input, &mut self.view_settings);
 
+        self.update_worker();
+
         let cursor_icon = if self.view_settings.edit_mode == EditMode::Brush {
             egui::CursorIcon::Default
         } else if self.view.ui_state.is_idle() && self.view.is_hovering_selection(&self.view_input)
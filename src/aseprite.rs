@@ -0,0 +1,483 @@
+//! Loads the first frame of Aseprite files as an image, see
+//! https://github.com/aseprite/aseprite/blob/main/docs/ase-file-specs.md
+//!
+//! Layers are stacked by copying their non-transparent pixels, without blending or opacity,
+//! because the alpha value of a pixel encodes its material class.
+
+use crate::{
+    field::RgbaField,
+    math::{point::Point, rect::Rect, rgba8::Rgba8},
+};
+use anyhow::{Context, bail};
+
+pub const EXTENSION: &str = "aseprite";
+
+const HEADER_MAGIC: u16 = 0xA5E0;
+const FRAME_MAGIC: u16 = 0xF1FA;
+const HEADER_SIZE: usize = 128;
+
+const OLD_PALETTE_CHUNK: u16 = 0x0004;
+const LAYER_CHUNK: u16 = 0x2004;
+const CEL_CHUNK: u16 = 0x2005;
+const PALETTE_CHUNK: u16 = 0x2019;
+
+/// Largest number of pixels of the canvas and of a cel, larger ones are rejected before any memory
+/// is allocated for them.
+const MAX_PIXELS: usize = 1 << 24;
+
+/// Indexed pixels are single bytes, so later palette entries are never used
+const MAX_PALETTE_SIZE: usize = 256;
+
+const LAYER_VISIBLE_FLAG: u16 = 1;
+const LAYER_GROUP_TYPE: u16 = 1;
+
+const RAW_CEL_TYPE: u16 = 0;
+const LINKED_CEL_TYPE: u16 = 1;
+const COMPRESSED_CEL_TYPE: u16 = 2;
+
+#[derive(Debug, Clone)]
+pub struct Layer {
+    pub name: String,
+    pub visible: bool,
+    pub is_group: bool,
+
+    /// Nesting depth, the parent of a layer is the previous layer with a smaller level.
+    pub child_level: u16,
+}
+
+#[derive(Debug, Clone)]
+struct Cel {
+    i_layer: usize,
+    image: RgbaField,
+}
+
+/// First frame of an Aseprite file
+#[derive(Debug, Clone)]
+pub struct AsepriteFile {
+    pub bounds: Rect<i64>,
+
+    /// From bottom to top
+    pub layers: Vec<Layer>,
+    cels: Vec<Cel>,
+}
+
+/// Little endian reader over the bytes of the file
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> anyhow::Result<&'a [u8]> {
+        if self.bytes.len() < n {
+            bail!("Unexpected end of aseprite file");
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> anyhow::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn i16(&mut self) -> anyhow::Result<i16> {
+        Ok(i16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn string(&mut self) -> anyhow::Result<String> {
+        let len = self.u16()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+}
+
+/// How the pixels of cels are stored
+#[derive(Debug, Clone, Copy)]
+enum ColorDepth {
+    Rgba,
+    Grayscale,
+    Indexed { transparent_index: u8 },
+}
+
+impl ColorDepth {
+    fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::Rgba => 4,
+            Self::Grayscale => 2,
+            Self::Indexed { .. } => 1,
+        }
+    }
+
+    fn rgba(self, bytes: &[u8], palette: &[Rgba8]) -> Rgba8 {
+        match self {
+            Self::Rgba => Rgba8::new(bytes[0], bytes[1], bytes[2], bytes[3]),
+            Self::Grayscale => Rgba8::new(bytes[0], bytes[0], bytes[0], bytes[1]),
+            Self::Indexed { transparent_index } if bytes[0] == transparent_index => {
+                Rgba8::TRANSPARENT
+            }
+            Self::Indexed { .. } => palette
+                .get(bytes[0] as usize)
+                .copied()
+                .unwrap_or(Rgba8::TRANSPARENT),
+        }
+    }
+}
+
+impl AsepriteFile {
+    /// True if `content` has the magic number of an Aseprite file
+    pub fn is_aseprite(content: &[u8]) -> bool {
+        content.len() >= HEADER_SIZE && content[4..6] == HEADER_MAGIC.to_le_bytes()
+    }
+
+    pub fn decode(content: &[u8]) -> anyhow::Result<Self> {
+        if !Self::is_aseprite(content) {
+            bail!("Not an aseprite file");
+        }
+        let mut header = Reader {
+            bytes: &content[..HEADER_SIZE],
+        };
+        header.take(6)?;
+        let n_frames = header.u16()?;
+        let width = header.u16()?;
+        let height = header.u16()?;
+        let color_depth = header.u16()?;
+        header.take(4 + 2 + 4 + 4)?;
+        let transparent_index = header.u8()?;
+        let color_depth = match color_depth {
+            32 => ColorDepth::Rgba,
+            16 => ColorDepth::Grayscale,
+            8 => ColorDepth::Indexed { transparent_index },
+            _ => bail!("Unsupported color depth {color_depth}"),
+        };
+        if n_frames == 0 {
+            bail!("Aseprite file has no frames");
+        }
+        if width as usize * height as usize > MAX_PIXELS {
+            bail!("Aseprite canvas of {width}x{height} pixels is too large");
+        }
+
+        let mut reader = Reader {
+            bytes: &content[HEADER_SIZE..],
+        };
+        let frame_size = reader.u32()? as usize;
+        if reader.u16()? != FRAME_MAGIC {
+            bail!("Invalid aseprite frame");
+        }
+        let old_n_chunks = reader.u16()?;
+        reader.take(2 + 2)?;
+        let n_chunks = match reader.u32()? {
+            0 => old_n_chunks as u32,
+            n_chunks => n_chunks,
+        };
+        let frame_header_size = 16;
+        let mut frame = Reader {
+            bytes: reader
+                .take(frame_size.saturating_sub(frame_header_size))
+                .context("Truncated aseprite frame")?,
+        };
+
+        let mut layers = Vec::new();
+        let mut palette = Vec::new();
+        // Cels are decoded once the palette is known, which can come after them
+        let mut cel_chunks = Vec::new();
+        for _ in 0..n_chunks {
+            let chunk_size = frame.u32()? as usize;
+            let chunk_type = frame.u16()?;
+            let mut chunk = Reader {
+                bytes: frame.take(chunk_size.saturating_sub(6))?,
+            };
+            match chunk_type {
+                LAYER_CHUNK => layers.push(decode_layer(&mut chunk)?),
+                CEL_CHUNK => cel_chunks.push(chunk),
+                PALETTE_CHUNK => decode_palette(&mut chunk, &mut palette)?,
+                OLD_PALETTE_CHUNK if palette.is_empty() => {
+                    decode_old_palette(&mut chunk, &mut palette)?
+                }
+                _ => {}
+            }
+        }
+
+        let mut cels = Vec::new();
+        for mut chunk in cel_chunks {
+            if let Some(cel) = decode_cel(&mut chunk, color_depth, &palette)? {
+                cels.push(cel);
+            }
+        }
+
+        Ok(Self {
+            bounds: Rect::low_size(Point(0, 0), Point(width as i64, height as i64)),
+            layers,
+            cels,
+        })
+    }
+
+    /// Layers to show, either the visible ones or the layer named `layer_name` and, if it is a
+    /// group, its visible children.
+    fn shown_layers(&self, layer_name: Option<&str>) -> anyhow::Result<Vec<bool>> {
+        let selected = match layer_name {
+            Some(name) => Some(
+                self.layers
+                    .iter()
+                    .position(|layer| layer.name == name)
+                    .with_context(|| format!("No layer named {name:?}"))?,
+            ),
+            None => None,
+        };
+
+        // Indices of the parents of the current layer, outermost first
+        let mut parents: Vec<usize> = Vec::new();
+        let mut shown = Vec::with_capacity(self.layers.len());
+        for (i_layer, layer) in self.layers.iter().enumerate() {
+            parents.truncate(layer.child_level as usize);
+
+            // Parents below the selected layer must be visible, the ones above it don't matter
+            let visible_from = match selected {
+                None => Some(0),
+                Some(selected) if selected == i_layer => None,
+                Some(selected) => parents
+                    .iter()
+                    .position(|&i_parent| i_parent == selected)
+                    .map(|i| i + 1),
+            };
+            let is_shown = match visible_from {
+                None => Some(i_layer) == selected,
+                Some(from) => {
+                    layer.visible
+                        && parents[from..]
+                            .iter()
+                            .all(|&i_parent| self.layers[i_parent].visible)
+                }
+            };
+            shown.push(is_shown);
+            parents.push(i_layer);
+        }
+        Ok(shown)
+    }
+
+    /// Stack the cels of the shown layers, see `shown_layers`.
+    pub fn flatten(&self, layer_name: Option<&str>) -> anyhow::Result<RgbaField> {
+        let shown = self.shown_layers(layer_name)?;
+        let mut flattened = RgbaField::filled(self.bounds, Rgba8::TRANSPARENT);
+        let mut cels: Vec<_> = self
+            .cels
+            .iter()
+            .filter(|cel| shown.get(cel.i_layer) == Some(&true))
+            .collect();
+        cels.sort_by_key(|cel| cel.i_layer);
+        for cel in cels {
+            for index in cel.image.indices() {
+                let rgba = cel.image[index];
+                if rgba.a != 0 && self.bounds.half_open_contains(index) {
+                    flattened[index] = rgba;
+                }
+            }
+        }
+        Ok(flattened)
+    }
+}
+
+fn decode_layer(chunk: &mut Reader) -> anyhow::Result<Layer> {
+    let flags = chunk.u16()?;
+    let layer_type = chunk.u16()?;
+    let child_level = chunk.u16()?;
+    chunk.take(2 + 2 + 2 + 1 + 3)?;
+    let name = chunk.string()?;
+    Ok(Layer {
+        name,
+        visible: flags & LAYER_VISIBLE_FLAG != 0,
+        is_group: layer_type == LAYER_GROUP_TYPE,
+        child_level,
+    })
+}
+
+fn decode_palette(chunk: &mut Reader, palette: &mut Vec<Rgba8>) -> anyhow::Result<()> {
+    let size = chunk.u32()? as usize;
+    let first = chunk.u32()? as usize;
+    let last = chunk.u32()? as usize;
+    chunk.take(8)?;
+    // The size is untrusted
+    let size = size.min(MAX_PALETTE_SIZE);
+    palette.resize(size.max(palette.len()), Rgba8::TRANSPARENT);
+    for i in first..=last {
+        let flags = chunk.u16()?;
+        let [r, g, b, a] = [chunk.u8()?, chunk.u8()?, chunk.u8()?, chunk.u8()?];
+        if flags & 1 != 0 {
+            chunk.string()?;
+        }
+        if let Some(color) = palette.get_mut(i) {
+            *color = Rgba8::new(r, g, b, a);
+        }
+    }
+    Ok(())
+}
+
+fn decode_old_palette(chunk: &mut Reader, palette: &mut Vec<Rgba8>) -> anyhow::Result<()> {
+    let n_packets = chunk.u16()?;
+    let mut i = 0;
+    for _ in 0..n_packets {
+        i += chunk.u8()? as usize;
+        let n_colors = match chunk.u8()? {
+            0 => 256,
+            n_colors => n_colors as usize,
+        };
+        for _ in 0..n_colors {
+            let [r, g, b] = [chunk.u8()?, chunk.u8()?, chunk.u8()?];
+            if i < MAX_PALETTE_SIZE {
+                if palette.len() <= i {
+                    palette.resize(i + 1, Rgba8::TRANSPARENT);
+                }
+                palette[i] = Rgba8::new(r, g, b, 255);
+            }
+            i += 1;
+        }
+    }
+    Ok(())
+}
+
+/// None for cels without an image in the first frame
+fn decode_cel(
+    chunk: &mut Reader,
+    color_depth: ColorDepth,
+    palette: &[Rgba8],
+) -> anyhow::Result<Option<Cel>> {
+    let i_layer = chunk.u16()? as usize;
+    let x = chunk.i16()? as i64;
+    let y = chunk.i16()? as i64;
+    chunk.take(1)?;
+    let cel_type = chunk.u16()?;
+    chunk.take(2 + 5)?;
+
+    // Linked cels refer to other frames, tilemaps are not supported
+    match cel_type {
+        RAW_CEL_TYPE | COMPRESSED_CEL_TYPE => {}
+        LINKED_CEL_TYPE => return Ok(None),
+        _ => bail!("Unsupported cel type {cel_type}"),
+    }
+
+    let width = chunk.u16()? as usize;
+    let height = chunk.u16()? as usize;
+    if width * height > MAX_PIXELS {
+        bail!("Aseprite cel of {width}x{height} pixels is too large");
+    }
+    let bytes_per_pixel = color_depth.bytes_per_pixel();
+    let size = width * height * bytes_per_pixel;
+    let pixels = if cel_type == RAW_CEL_TYPE {
+        chunk.take(size)?.to_vec()
+    } else {
+        let pixels = miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(chunk.bytes, size)
+            .map_err(|err| anyhow::anyhow!("Invalid compressed cel: {err}"))?;
+        if pixels.len() < size {
+            bail!("Compressed cel too short");
+        }
+        pixels
+    };
+
+    let bounds = Rect::low_size(Point(x, y), Point(width as i64, height as i64));
+    let colors = pixels
+        .chunks_exact(bytes_per_pixel)
+        .take(width * height)
+        .map(|bytes| color_depth.rgba(bytes, palette))
+        .collect();
+    Ok(Some(Cel {
+        i_layer,
+        image: RgbaField::from_linear(bounds, colors),
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        aseprite::{
+            AsepriteFile, COMPRESSED_CEL_TYPE, ColorDepth, HEADER_MAGIC, HEADER_SIZE, Reader,
+            decode_cel, decode_old_palette, decode_palette,
+        },
+        field::RgbaField,
+        math::rgba8::Rgb,
+        pixmap::MaterialMap,
+    };
+
+    fn load(path: &str) -> AsepriteFile {
+        AsepriteFile::decode(&std::fs::read(path).unwrap()).unwrap()
+    }
+
+    /// The tutorials are exported from these files, transparent pixels only differ in color.
+    #[test]
+    fn flatten_tutorials() {
+        for (aseprite_path, png_path) in [
+            ("tutorial/basics.aseprite", "tutorial/tutorial_basics.png"),
+            ("tutorial/solid.aseprite", "tutorial/tutorial_solid.png"),
+        ] {
+            let flattened = load(aseprite_path).flatten(None).unwrap();
+            let exported = RgbaField::load(png_path).unwrap();
+            assert_eq!(MaterialMap::from(flattened), MaterialMap::from(exported));
+        }
+    }
+
+    #[test]
+    fn select_layer() {
+        let aseprite = load("tutorial/basics.aseprite");
+        let text = aseprite.flatten(Some("text")).unwrap();
+        let bitmaps = aseprite.flatten(Some("bitmaps")).unwrap();
+        let all = aseprite.flatten(None).unwrap();
+
+        // Bitmaps is above text
+        for index in all.indices() {
+            let expected = if bitmaps[index].a != 0 {
+                bitmaps[index]
+            } else {
+                text[index]
+            };
+            assert_eq!(all[index], expected);
+        }
+        assert!(!text.is_zero() && !bitmaps.is_zero());
+        assert!(aseprite.flatten(Some("missing")).is_err());
+    }
+
+    /// Canvas and cel sizes from the file are checked before they are allocated.
+    #[test]
+    fn untrusted_image_sizes() {
+        let mut header = vec![0; HEADER_SIZE];
+        header[4..6].copy_from_slice(&HEADER_MAGIC.to_le_bytes());
+        for (offset, value) in [(6, 1), (8, u16::MAX), (10, u16::MAX), (12, 32)] {
+            header[offset..offset + 2].copy_from_slice(&u16::to_le_bytes(value));
+        }
+        let err = AsepriteFile::decode(&header).unwrap_err();
+        assert!(err.to_string().contains("too large"));
+
+        // A 1x1 cel whose pixels decompress to much more than 4 bytes
+        let mut cel = vec![0; 16];
+        cel[7..9].copy_from_slice(&COMPRESSED_CEL_TYPE.to_le_bytes());
+        cel.extend([1, 0, 1, 0]);
+        cel.extend(miniz_oxide::deflate::compress_to_vec_zlib(&[0; 1 << 16], 6));
+        let err = decode_cel(&mut Reader { bytes: &cel }, ColorDepth::Rgba, &[]).unwrap_err();
+        assert!(err.to_string().contains("Invalid compressed cel"));
+    }
+
+    /// Palette sizes and indices from the file don't allocate more than 256 entries.
+    #[test]
+    fn untrusted_palette_sizes() {
+        let mut bytes = Vec::new();
+        for value in [u32::MAX, 0, 0] {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.extend([0; 8]);
+        bytes.extend([0, 0, 1, 2, 3, 255]);
+        let mut palette = Vec::new();
+        decode_palette(&mut Reader { bytes: &bytes }, &mut palette).unwrap();
+        assert_eq!(palette.len(), 256);
+
+        // Two packets that each skip 255 entries and set one color
+        let bytes = [2, 0, 255, 1, 1, 2, 3, 255, 1, 4, 5, 6];
+        let mut palette = Vec::new();
+        decode_old_palette(&mut Reader { bytes: &bytes }, &mut palette).unwrap();
+        assert_eq!(palette.len(), 256);
+        assert_eq!(palette[255].rgb(), Rgb(1, 2, 3));
+    }
+}
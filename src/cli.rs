@@ -0,0 +1,230 @@
+//! Command line interface, `topolang_bin <command> <args>`. Without a command the editor is started.
+
+use crate::{
+    compiler::{Compiler, Program},
+    graph_export::{Json, morphism_dot, morphism_json, topology_dot, topology_json},
+    interpreter::{Interpreter, StabilizeOutcome},
+    legacy_migration::{describe, migrate_file, world_files},
+    lints::lint_program,
+    rule::CanvasInput,
+    rule_stats::{rule_label, stats_csv},
+    solver::plan::SearchBudget,
+    svg_export::{SvgOptions, topology_svg},
+    topology::MaskedTopology,
+    world::World,
+};
+use anyhow::{Context, bail};
+use std::path::Path;
+
+const USAGE: &str = "Usage: topolang_bin [<command> <args>]
+
+Commands:
+    lint <world.png>              Compile the world and print compile errors and lints
+    profile <world.png> [ticks]   Run the world for some ticks (default 100) and print the
+                                  statistics of each rule as csv
+    migrate <folder> [--dry-run]  Rewrite deprecated color encodings of all pngs in the folder
+                                  and its subfolders, --dry-run only reports them
+    svg <world.png> <out.svg> [--seams] [--corners]
+                                  Export the regions of the world as vector graphics, optionally
+                                  with seams and corners
+    graph <world.png> [--rule <index>] [--dot]
+                                  Print the regions, borders, seams and containment tree of the
+                                  world as json, or as a Graphviz graph with --dot. With --rule
+                                  print the matches of the rule with that index instead";
+
+/// Run the command given by `args` (without the program name).
+pub fn run(args: &[String]) -> anyhow::Result<()> {
+    match args {
+        [command, path] if command == "lint" => lint(Path::new(path)),
+        [command, path] if command == "profile" => profile(Path::new(path), 100),
+        [command, path, ticks] if command == "profile" => {
+            let ticks = ticks.parse().context("Invalid number of ticks")?;
+            profile(Path::new(path), ticks)
+        }
+        [command, folder] if command == "migrate" => migrate(Path::new(folder), false),
+        [command, folder, flag] if command == "migrate" && flag == "--dry-run" => {
+            migrate(Path::new(folder), true)
+        }
+        [command, path, svg_path, flags @ ..] if command == "svg" => {
+            let mut options = SvgOptions::default();
+            for flag in flags {
+                match flag.as_str() {
+                    "--seams" => options.seams = true,
+                    "--corners" => options.corners = true,
+                    _ => bail!("{USAGE}"),
+                }
+            }
+            svg(Path::new(path), Path::new(svg_path), options)
+        }
+        [command, path, flags @ ..] if command == "graph" => {
+            let mut dot = false;
+            let mut rule = None;
+            let mut flags = flags.iter();
+            while let Some(flag) = flags.next() {
+                match flag.as_str() {
+                    "--dot" => dot = true,
+                    "--rule" => {
+                        let index = flags.next().context("Missing rule index")?;
+                        rule = Some(index.parse().context("Invalid rule index")?);
+                    }
+                    _ => bail!("{USAGE}"),
+                }
+            }
+            match rule {
+                Some(i_rule) => graph_rule(Path::new(path), i_rule, dot),
+                None => graph(Path::new(path), dot),
+            }
+        }
+        _ => bail!("{USAGE}"),
+    }
+}
+
+/// Load and compile a world, imports are resolved relative to the world's folder. Compile errors
+/// are printed.
+fn load_and_compile(path: &Path) -> anyhow::Result<(World, Program)> {
+    let world = World::load(path).with_context(|| format!("Failed to load {path:?}"))?;
+    let folder = path.parent().unwrap_or(Path::new("."));
+    let compiler = Compiler::new().with_library_root(folder);
+
+    match compiler.compile(&world) {
+        Ok(program) => Ok((world, program)),
+        Err(errors) => {
+            for err in &errors {
+                match err.bounds.first() {
+                    Some(bounds) => {
+                        println!(
+                            "{}: error: {} at ({}, {})",
+                            path.display(),
+                            err.message,
+                            bounds.left(),
+                            bounds.top()
+                        )
+                    }
+                    None => println!("{}: error: {}", path.display(), err.message),
+                }
+            }
+            bail!("{} compile errors", errors.len());
+        }
+    }
+}
+
+fn lint(path: &Path) -> anyhow::Result<()> {
+    let (world, program) = load_and_compile(path)?;
+
+    let lints = lint_program(&program, &world);
+    for lint in &lints {
+        println!("{}: warning: {lint}", path.display());
+    }
+    println!("{} rules, {} warnings", program.rules.len(), lints.len());
+
+    Ok(())
+}
+
+fn profile(path: &Path, ticks: usize) -> anyhow::Result<()> {
+    let (mut world, program) = load_and_compile(path)?;
+
+    let mut interpreter = Interpreter::new(program);
+    for _ in 0..ticks {
+        let ticked = interpreter.tick(&mut world, &CanvasInput::default(), 1024);
+        if let StabilizeOutcome::SearchBudgetExceeded(i_rule) = ticked.stabilize_outcome {
+            let rule = &interpreter.program.rules[i_rule];
+            eprintln!(
+                "{}: warning: search of rule {} exceeded its budget, stopping",
+                path.display(),
+                rule_label(rule)
+            );
+            break;
+        }
+        if !ticked.changed() {
+            break;
+        }
+    }
+
+    let stats = interpreter.generic_rule_stats();
+    print!("{}", stats_csv(&interpreter.program, &stats));
+
+    Ok(())
+}
+
+fn svg(path: &Path, svg_path: &Path, options: SvgOptions) -> anyhow::Result<()> {
+    let world = World::load(path).with_context(|| format!("Failed to load {path:?}"))?;
+    std::fs::write(svg_path, topology_svg(world.topology(), options))
+        .with_context(|| format!("Failed to write {svg_path:?}"))?;
+    Ok(())
+}
+
+fn graph(path: &Path, dot: bool) -> anyhow::Result<()> {
+    let world = World::load(path).with_context(|| format!("Failed to load {path:?}"))?;
+    if dot {
+        print!("{}", topology_dot(world.topology()));
+    } else {
+        println!("{}", topology_json(world.topology()));
+    }
+    Ok(())
+}
+
+/// Matches of all instances of the generic rule `i_rule` in the world, each as a morphism from
+/// the before pattern.
+fn graph_rule(path: &Path, i_rule: usize, dot: bool) -> anyhow::Result<()> {
+    let (world, program) = load_and_compile(path)?;
+    let generic_rule = program.rules.get(i_rule).with_context(|| {
+        format!(
+            "Rule index out of range, the world has {} rules",
+            program.rules.len()
+        )
+    })?;
+
+    let codom = MaskedTopology::new(world.topology(), &program.source);
+    let mut matches = Vec::new();
+    for instance in &generic_rule.instances {
+        let pattern = &instance.rule.before;
+        let solutions = pattern
+            .search_strategy
+            .solutions(&codom, None, &SearchBudget::unlimited());
+        matches.extend(solutions.into_iter().map(|phi| (phi, &pattern.topology)));
+    }
+
+    if dot {
+        for (phi, pattern) in &matches {
+            print!("{}", morphism_dot(phi, pattern, world.topology()));
+        }
+    } else {
+        let matches = matches
+            .iter()
+            .map(|(phi, pattern)| morphism_json(phi, pattern, world.topology()))
+            .collect();
+        println!("{}", Json::Array(matches));
+    }
+    Ok(())
+}
+
+fn migrate(folder: &Path, dry_run: bool) -> anyhow::Result<()> {
+    let files = world_files(folder)?;
+    let mut migrated = 0;
+    let mut failed = 0;
+    for path in &files {
+        match migrate_file(path, dry_run) {
+            Ok(legacy_pixels) if legacy_pixels.is_empty() => {}
+            Ok(legacy_pixels) => {
+                let action = if dry_run { "would migrate" } else { "migrated" };
+                println!("{}: {action} {}", path.display(), describe(&legacy_pixels));
+                migrated += 1;
+            }
+            Err(err) => {
+                eprintln!("{}: error: {err}", path.display());
+                failed += 1;
+            }
+        }
+    }
+
+    let action = if dry_run {
+        "need migration"
+    } else {
+        "migrated"
+    };
+    println!("{migrated} of {} files {action}", files.len());
+    if failed > 0 {
+        bail!("{failed} files failed");
+    }
+    Ok(())
+}
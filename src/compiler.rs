@@ -25,6 +25,7 @@ use crate::{
 };
 use ahash::{HashMap, HashSet};
 use itertools::Itertools;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone)]
 pub struct RuleInstance {
@@ -44,6 +45,10 @@ pub struct RuleSource {
     pub after_outer_border: Border,
     pub bounds: Rect<i64>,
     pub modified_time: AtomicTime,
+
+    /// File the rule was imported from, None if the rule is part of the compiled world. If set,
+    /// `keys`, borders and `bounds` refer to the imported world.
+    pub library: Option<PathBuf>,
 }
 
 impl RuleSource {
@@ -60,6 +65,15 @@ impl RuleSource {
         area.extend(area_left_of_boundary(self.after_outer_border.iter_sides()));
         area
     }
+
+    /// True if both sources consist of the same regions and none of them was modified in between.
+    /// Regions of imported rules belong to a different Topology, so they are never unchanged.
+    pub fn is_unchanged(&self, other: &RuleSource) -> bool {
+        self.library.is_none()
+            && other.library.is_none()
+            && self.modified_time == other.modified_time
+            && self.keys == other.keys
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -81,9 +95,17 @@ pub struct PlaceholderRange {
 
     /// Each translated to origin, not empty
     pub choices: Vec<MaterialMap>,
+
+    /// File the range was imported from, None if the range is part of the compiled world.
+    pub library: Option<PathBuf>,
 }
 
 impl PlaceholderRange {
+    /// Same placeholder and choices, the source can differ.
+    pub fn same_substitutions(&self, other: &PlaceholderRange) -> bool {
+        self.placeholder == other.placeholder && self.choices == other.choices
+    }
+
     pub fn new(
         placeholder: MaterialMap,
         items: Vec<MaterialMap>,
@@ -102,10 +124,25 @@ impl PlaceholderRange {
             placeholder,
             choices: items,
             source,
+            library: None,
         }
     }
 }
 
+/// Region of `Material::RULE_IMPORT` with a single hole that contains the path of a world, encoded
+/// using `MaterialMap::encode_bytes`. The rules and placeholder ranges of the imported world are
+/// added to the program.
+#[derive(Debug, Clone)]
+pub struct RuleImport {
+    /// Relative to the folder of the importing world
+    pub path: PathBuf,
+
+    /// Import frame and the encoded path
+    pub source: HashSet<RegionKey>,
+
+    pub bounds: Rect<i64>,
+}
+
 pub struct PlaceholderSubstitution<'a> {
     /// Placeholder to be replaced
     pub placeholder: MaterialMap,
@@ -150,9 +187,18 @@ impl CompileError {
 
 #[derive(Debug, Clone)]
 pub struct Program {
+    /// Rules of the compiled world first, followed by imported rules.
     pub rules: Vec<GenericRule>,
+
+    /// Regions of the compiled world that define the program and are hidden during execution.
     pub source: HashSet<RegionKey>,
+
+    /// Bounds of the local rule frames, choice lists and imports that make up `source`
+    pub source_bounds: Vec<Rect<i64>>,
+
     pub placeholder_ranges: Vec<PlaceholderRange>,
+
+    pub imports: Vec<RuleImport>,
 }
 
 impl Program {
@@ -169,6 +215,61 @@ impl Program {
     pub fn rule_instances_len(&self) -> usize {
         self.rules.iter().map(|rule| rule.instances.len()).sum()
     }
+
+    /// Index in `rules` of the generic rule that the `i_instance`-th rule instance of
+    /// `iter_rule_instances` belongs to.
+    pub fn generic_rule_index(&self, i_instance: usize) -> usize {
+        let mut offset = 0;
+        for (i_rule, rule) in self.rules.iter().enumerate() {
+            offset += rule.instances.len();
+            if i_instance < offset {
+                return i_rule;
+            }
+        }
+        panic!("Rule instance index out of bounds");
+    }
+
+    /// True if `topology` was modified after `since` in a way that requires recompiling, e.g. a
+    /// rule frame was edited, created or removed.
+    /// Only the regions modified after `since` are visited, unless one of them overlaps the
+    /// source, then the source is checked for removed regions.
+    pub fn is_outdated(&self, topology: &Topology, since: AtomicTime) -> bool {
+        let mut overlaps_source = false;
+        for (_, region_key) in topology.modifications_after(since) {
+            let region = &topology[region_key];
+            let bounds = region.bounds();
+            if region.material.is_rule()
+                || self.source.contains(&region_key)
+                || self
+                    .source_bounds
+                    .iter()
+                    .any(|source_bounds| source_bounds.contains_rect(bounds))
+            {
+                return true;
+            }
+
+            overlaps_source |= self
+                .source_bounds
+                .iter()
+                .any(|source_bounds| source_bounds.intersects(bounds));
+        }
+
+        // A source region can only be removed by drawing over it.
+        overlaps_source
+            && !self
+                .source
+                .iter()
+                .all(|region_key| topology.regions.contains_key(region_key))
+    }
+
+    /// Rule in `self` compiled from the same unchanged source as `source`
+    pub fn unchanged_rule(&self, source: &RuleSource) -> Option<&GenericRule> {
+        self.rules.iter().find(|rule| {
+            rule.source
+                .as_ref()
+                .is_some_and(|rule_source| rule_source.is_unchanged(source))
+        })
+    }
 }
 
 /// ┌────────────────┐
@@ -178,6 +279,7 @@ impl Program {
 /// │   │        │   │
 /// │   └────────┘   │
 /// └────────────────┘
+#[derive(Clone)]
 pub struct Symbol {
     pattern: Pattern,
     outer_border_key: BorderKey,
@@ -228,39 +330,41 @@ impl Symbol {
     ) -> Vec<RegionKey> {
         let mut tagged = Vec::new();
 
-        let solutions = self
-            .pattern
+        // Each match is handled as soon as it is found, without collecting them first
+        self.pattern
             .search_strategy
             .main_plan
-            .solutions(&topology.into());
-
-        for phi in solutions {
-            let phi_outer_border_key = phi[self.outer_border_key];
-            let phi_outer_border = &topology[phi_outer_border_key];
-
-            // Border must be a single loop seam
-            let Ok(phi_outer_seam) = phi_outer_border.atomic_seams().exactly_one() else {
-                println!("Symbol cannot be surrounded by multiple different materials.");
-                continue;
-            };
+            .search(&topology.into(), |phi| {
+                let phi_outer_border_key = phi[self.outer_border_key];
+                let phi_outer_border = &topology[phi_outer_border_key];
+
+                // Border must be a single loop seam
+                let Ok(phi_outer_seam) = phi_outer_border.atomic_seams().exactly_one() else {
+                    println!("Symbol cannot be surrounded by multiple different materials.");
+                    return;
+                };
 
-            let surrounding_region_key = topology.right_of(phi_outer_seam).unwrap();
-            let surrounding_region = &topology[surrounding_region_key];
+                let surrounding_region_key = topology.right_of(phi_outer_seam).unwrap();
+                let surrounding_region = &topology[surrounding_region_key];
 
-            // Remove left side of phi_outer_border (the symbol) and fill with the surrounding
-            // material.
-            material_map.fill_left_of_border(phi_outer_border, surrounding_region.material);
+                // Remove left side of phi_outer_border (the symbol) and fill with the surrounding
+                // material.
+                material_map.fill_left_of_border(phi_outer_border, surrounding_region.material);
 
-            tagged.push(surrounding_region_key);
-        }
+                tagged.push(surrounding_region_key);
+            });
 
         tagged
     }
 }
 
+#[derive(Clone)]
 pub struct Compiler {
     rule_frame: Topology,
 
+    /// Folder that imports of the compiled world are resolved against.
+    library_root: PathBuf,
+
     before_outer_border_key: BorderKey,
 
     after_outer_border_key: BorderKey,
@@ -300,9 +404,19 @@ impl Compiler {
             after_outer_border_key: BorderKey::new(after_frame.key(), 0),
             input_event_symbols,
             rule_frame,
+            library_root: PathBuf::from("."),
         }
     }
 
+    pub fn set_library_root(&mut self, library_root: impl Into<PathBuf>) {
+        self.library_root = library_root.into();
+    }
+
+    pub fn with_library_root(mut self, library_root: impl Into<PathBuf>) -> Self {
+        self.set_library_root(library_root);
+        self
+    }
+
     pub fn compile_pattern(
         &self,
         mut material_map: MaterialMap,
@@ -406,11 +520,13 @@ impl Compiler {
         Ok(placeholder_range)
     }
 
+    /// Errors of individual choice frames are pushed to `errors` and the frame is skipped.
     #[inline(never)]
     pub fn compile_placeholder_ranges(
         material_map: &MaterialMap,
         topology: &Topology,
-    ) -> Result<Vec<PlaceholderRange>, CompileError> {
+        errors: &mut Vec<CompileError>,
+    ) -> Vec<PlaceholderRange> {
         let mut placeholder_ranges = Vec::new();
 
         for placeholder_frame_candidate in topology.regions.values() {
@@ -418,16 +534,17 @@ impl Compiler {
                 continue;
             }
 
-            let placeholder_range = Self::compile_placeholder_range(
+            match Self::compile_placeholder_range(
                 material_map,
                 topology,
                 placeholder_frame_candidate,
-            )
-            .map_err(|err| err.with_bounds(placeholder_frame_candidate.bounds()))?;
-            placeholder_ranges.push(placeholder_range);
+            ) {
+                Ok(placeholder_range) => placeholder_ranges.push(placeholder_range),
+                Err(err) => errors.push(err.with_bounds(placeholder_frame_candidate.bounds())),
+            }
         }
 
-        Ok(placeholder_ranges)
+        placeholder_ranges
     }
 
     fn placeholders(material_map: &MaterialMap, topology: &Topology) -> Vec<MaterialMap> {
@@ -574,16 +691,21 @@ impl Compiler {
             after_outer_border: phi_after_outer_border,
             bounds,
             modified_time,
+            library: None,
         }
     }
 
+    /// Rules whose source is unchanged in `previous` are reused instead of being compiled again.
+    /// Errors of individual rule frames are pushed to `errors` and the rule is skipped.
     #[inline(never)]
     pub fn compile_rules(
         &self,
         material_map: &MaterialMap,
         topology: &Topology,
         placeholder_ranges: &Vec<PlaceholderRange>,
-    ) -> Result<Vec<GenericRule>, CompileError> {
+        previous: Option<&Program>,
+        errors: &mut Vec<CompileError>,
+    ) -> Vec<GenericRule> {
         let _tracy_span = tracy_client::span!("compile_rules");
 
         let masked_topology = MaskedTopology::whole(topology);
@@ -607,6 +729,13 @@ impl Compiler {
         for phi in matches {
             let source = self.compile_source(topology, &phi);
 
+            if let Some(previous_rule) =
+                previous.and_then(|previous| previous.unchanged_rule(&source))
+            {
+                rules.push(previous_rule.clone());
+                continue;
+            }
+
             // The before material map is the before frame and everything it contains except
             // RULE_BEFORE
             let before_material_map = material_map
@@ -624,14 +753,18 @@ impl Compiler {
                 - source.after_outer_border.min_side().left_pixel;
             let after_material_map = after_material_map.translated(offset);
 
-            let rule_instances = self
-                .compile_rule_instances(
-                    before_material_map,
-                    after_material_map,
-                    &guess_chooser,
-                    &placeholder_ranges,
-                )
-                .map_err(|err| err.with_bounds(source.bounds))?;
+            let rule_instances = match self.compile_rule_instances(
+                before_material_map,
+                after_material_map,
+                &guess_chooser,
+                &placeholder_ranges,
+            ) {
+                Ok(rule_instances) => rule_instances,
+                Err(err) => {
+                    errors.push(err.with_bounds(source.bounds));
+                    continue;
+                }
+            };
 
             let generic_rule = GenericRule {
                 instances: rule_instances,
@@ -644,42 +777,256 @@ impl Compiler {
         // Sort rules by y coordinates of bounding box
         rules.sort_by_key(|rule| rule.source.as_ref().unwrap().bounds.top());
 
-        Ok(rules)
+        rules
+    }
+
+    #[inline(never)]
+    pub fn compile_import(
+        material_map: &MaterialMap,
+        topology: &Topology,
+        frame_region: &Region,
+    ) -> Result<RuleImport, CompileError> {
+        let Ok(hole_border) = frame_region.boundary.holes().iter().exactly_one() else {
+            return CompileError::err("Import frame must contain exactly one hole");
+        };
+
+        let encoded_path = material_map
+            .right_of_border(hole_border)
+            .without(Material::BLACK);
+        let Ok(path) = String::from_utf8(encoded_path.decode_bytes()) else {
+            return CompileError::err("Import path is not valid UTF-8");
+        };
+
+        if path.is_empty() {
+            return CompileError::err("Import path is empty");
+        }
+
+        let source = topology
+            .regions_left_of_border(frame_region.boundary.outer_border())
+            .map(Region::key)
+            .collect();
+
+        Ok(RuleImport {
+            path: PathBuf::from(path),
+            source,
+            bounds: frame_region.bounds(),
+        })
+    }
+
+    /// Errors of individual import frames are pushed to `errors` and the import is skipped.
+    #[inline(never)]
+    pub fn compile_imports(
+        material_map: &MaterialMap,
+        topology: &Topology,
+        errors: &mut Vec<CompileError>,
+    ) -> Vec<RuleImport> {
+        let mut imports = Vec::new();
+
+        for import_frame_candidate in topology.regions.values() {
+            if import_frame_candidate.material != Material::RULE_IMPORT {
+                continue;
+            }
+
+            match Self::compile_import(material_map, topology, import_frame_candidate) {
+                Ok(import) => imports.push(import),
+                Err(err) => errors.push(err.with_bounds(import_frame_candidate.bounds())),
+            }
+        }
+
+        // Deterministic order, rules of earlier imports have precedence.
+        imports.sort_by_key(|import| (import.bounds.top(), import.bounds.left()));
+
+        imports
+    }
+
+    /// Compile the world `import.path` relative to `folder`. `importing` contains the canonical
+    /// paths of all worlds that are currently being compiled, to detect cyclic imports. Errors
+    /// don't have bounds because they are not located in the importing world.
+    fn compile_library(
+        &self,
+        folder: &Path,
+        import: &RuleImport,
+        importing: &mut Vec<PathBuf>,
+    ) -> Result<Program, Vec<CompileError>> {
+        let path = folder.join(&import.path);
+        let Ok(canonical_path) = path.canonicalize() else {
+            return Err(vec![CompileError::new(format!(
+                "Imported file {path:?} not found"
+            ))]);
+        };
+
+        if importing.contains(&canonical_path) {
+            return Err(vec![CompileError::new(format!(
+                "Cyclic import of {path:?}"
+            ))]);
+        }
+
+        let world = match World::load(&canonical_path) {
+            Ok(world) => world,
+            Err(err) => {
+                return Err(vec![CompileError::new(format!(
+                    "Failed to load {path:?}: {err}"
+                ))]);
+            }
+        };
+
+        let library_folder = canonical_path.parent().unwrap_or(Path::new("."));
+
+        importing.push(canonical_path.clone());
+        let program = self.compile_world(&world, library_folder, importing, None);
+        importing.pop();
+
+        let mut program = program.map_err(|errors| {
+            errors
+                .into_iter()
+                .map(|err| CompileError::new(format!("In imported file {path:?}: {}", err.message)))
+                .collect::<Vec<_>>()
+        })?;
+
+        // Rules and ranges from nested imports keep their own library
+        for rule in &mut program.rules {
+            if let Some(source) = &mut rule.source {
+                source.library.get_or_insert_with(|| canonical_path.clone());
+            }
+        }
+
+        for placeholder_range in &mut program.placeholder_ranges {
+            placeholder_range
+                .library
+                .get_or_insert_with(|| canonical_path.clone());
+        }
+
+        Ok(program)
     }
 
-    pub fn compile(&self, world: &World) -> Result<Program, CompileError> {
+    /// Compiles as much as possible and returns all errors, sorted by position.
+    fn compile_world(
+        &self,
+        world: &World,
+        folder: &Path,
+        importing: &mut Vec<PathBuf>,
+        previous: Option<&Program>,
+    ) -> Result<Program, Vec<CompileError>> {
         let topology = world.topology();
         let material_map = world.material_map();
 
-        // Compile choice lists
-        let placeholder_ranges = Self::compile_placeholder_ranges(material_map, topology)?;
+        let mut errors = Vec::new();
+
+        let imports = Self::compile_imports(material_map, topology, &mut errors);
 
-        let rules = self.compile_rules(material_map, topology, &placeholder_ranges)?;
+        let mut imported_rules = Vec::new();
+        let mut imported_placeholder_ranges = Vec::new();
+        for import in &imports {
+            match self.compile_library(folder, import, importing) {
+                Ok(library) => {
+                    imported_rules.extend(library.rules);
+                    imported_placeholder_ranges.extend(library.placeholder_ranges);
+                }
+                Err(library_errors) => errors.extend(
+                    library_errors
+                        .into_iter()
+                        .map(|err| err.with_bounds(import.bounds)),
+                ),
+            }
+        }
+
+        // Compile choice lists, local ranges have precedence over imported ones
+        let mut placeholder_ranges =
+            Self::compile_placeholder_ranges(material_map, topology, &mut errors);
+        placeholder_ranges.extend(imported_placeholder_ranges);
+
+        // Rule instances depend on the placeholder ranges, if they changed nothing can be reused.
+        let previous = previous.filter(|previous| {
+            previous.placeholder_ranges.len() == placeholder_ranges.len()
+                && previous
+                    .placeholder_ranges
+                    .iter()
+                    .zip(&placeholder_ranges)
+                    .all(|(lhs, rhs)| lhs.same_substitutions(rhs))
+        });
+
+        let mut rules = self.compile_rules(
+            material_map,
+            topology,
+            &placeholder_ranges,
+            previous,
+            &mut errors,
+        );
+        rules.extend(imported_rules);
+
+        if !errors.is_empty() {
+            errors.sort_by_key(|err| {
+                err.bounds
+                    .first()
+                    .map(|bounds| (bounds.top(), bounds.left()))
+            });
+            return Err(errors);
+        }
 
+        // Only the source of local rules and ranges is part of `topology`.
         let mut source = HashSet::default();
+        let mut source_bounds = Vec::new();
         for rule in &rules {
-            if let Some(rule_source) = &rule.source {
-                source.extend(rule_source.keys.iter().copied())
+            if let Some(rule_source) = &rule.source
+                && rule_source.library.is_none()
+            {
+                source.extend(rule_source.keys.iter().copied());
+                source_bounds.push(rule_source.bounds);
             }
         }
 
         for placeholder_range in &placeholder_ranges {
-            source.extend(placeholder_range.source.iter().copied())
+            if placeholder_range.library.is_none() {
+                source.extend(placeholder_range.source.iter().copied());
+                source_bounds.push(Rect::iter_bounds(
+                    placeholder_range
+                        .source
+                        .iter()
+                        .map(|&region_key| topology[region_key].bounds()),
+                ));
+            }
+        }
+
+        for import in &imports {
+            source.extend(import.source.iter().copied());
+            source_bounds.push(import.bounds);
         }
 
         Ok(Program {
             rules,
             source,
+            source_bounds,
             placeholder_ranges,
+            imports,
         })
     }
+
+    pub fn compile(&self, world: &World) -> Result<Program, Vec<CompileError>> {
+        self.compile_world(world, &self.library_root, &mut Vec::new(), None)
+    }
+
+    /// Like `compile` but rules whose rule frame is unchanged since `previous` was compiled are
+    /// reused including their `SearchStrategy`. Imported rules are always compiled again.
+    pub fn recompile(
+        &self,
+        world: &World,
+        previous: &Program,
+    ) -> Result<Program, Vec<CompileError>> {
+        self.compile_world(world, &self.library_root, &mut Vec::new(), Some(previous))
+    }
 }
 
 #[cfg(test)]
 mod test {
     use crate::{
-        compiler::Compiler, material::Material, math::rgba8::Rgb, pixmap::MaterialMap,
-        rule::InputEvent, solver::plan::SimpleGuessChooser, world::World,
+        compiler::Compiler,
+        interpreter::{Interpreter, StabilizeOutcome},
+        material::Material,
+        math::{pixel::Pixel, point::Point, rect::Rect, rgba8::Rgb},
+        pixmap::MaterialMap,
+        rule::{CanvasInput, InputEvent},
+        solver::plan::SimpleGuessChooser,
+        world::World,
     };
     use ahash::HashMap;
 
@@ -797,4 +1144,107 @@ mod test {
     fn rule_placeholder_substitution_before_and_after() {
         assert_rule_placeholder_substitution("before_and_after");
     }
+
+    /// Rule defined in library.png is applied to world.png
+    #[test]
+    fn import() {
+        let folder = "test_resources/compiler/import";
+        let compiler = Compiler::new().with_library_root(folder);
+
+        let mut world = World::load(format!("{folder}/world.png")).unwrap();
+        let program = compiler.compile(&world).unwrap();
+        assert_eq!(program.imports.len(), 1);
+        assert_eq!(program.rules.len(), 1);
+
+        let library = program.rules[0].source.as_ref().unwrap().library.as_ref();
+        assert!(library.unwrap().ends_with("library.png"));
+
+        let mut interpreter = Interpreter::new(program);
+        let (outcome, applications) =
+            interpreter.stabilize(&mut world, &CanvasInput::default(), 64, f64::INFINITY);
+        assert_eq!(outcome, StabilizeOutcome::Stable);
+        assert_eq!(applications.len(), 1);
+
+        let expected = MaterialMap::load(format!("{folder}/world_expected.png")).unwrap();
+        assert_eq!(world.material_map(), &expected);
+    }
+
+    #[test]
+    fn import_cyclic() {
+        let folder = "test_resources/compiler/import_cyclic";
+        let compiler = Compiler::new().with_library_root(folder);
+
+        let world = World::load(format!("{folder}/cyclic.png")).unwrap();
+        let errors = compiler.compile(&world).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("Cyclic import"));
+        assert_eq!(errors[0].bounds.len(), 1);
+    }
+
+    /// All errors are reported, not only the first one.
+    #[test]
+    fn multiple_errors() {
+        let folder = "test_resources/compiler/errors";
+        let compiler = Compiler::new().with_library_root(folder);
+
+        let world = World::load(format!("{folder}/world.png")).unwrap();
+        let errors = compiler.compile(&world).unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|err| err.message.as_str()).collect();
+        assert_eq!(messages.len(), 5);
+        assert!(messages[0].contains("missing_a.png"));
+        assert_eq!(messages[1], "No range defined for the given placeholder");
+        assert_eq!(
+            messages[2],
+            "Each after side solid region must overlap a solid region in the before side"
+        );
+        assert!(messages[3].contains("missing_b.png"));
+        assert_eq!(messages[4], "Must have a placeholder");
+
+        // Errors are sorted by position and point at the frame they belong to
+        let frame_bounds: Vec<_> = errors
+            .iter()
+            .map(|err| *err.bounds.last().unwrap())
+            .collect();
+        assert_eq!(
+            frame_bounds,
+            [
+                Rect::low_high(Point(1, 1), Point(15, 12)),
+                Rect::low_high(Point(17, 1), Point(31, 7)),
+                Rect::low_high(Point(17, 9), Point(31, 15)),
+                Rect::low_high(Point(1, 13), Point(15, 24)),
+                Rect::low_high(Point(17, 17), Point(24, 24)),
+            ]
+        );
+    }
+
+    /// Only edits of rule frames make a program outdated.
+    #[test]
+    fn outdated() {
+        let mut world = World::load("test_resources/compiler/basic_1/world.png").unwrap();
+        let compiler = Compiler::new();
+        let program = compiler.compile(&world).unwrap();
+
+        let yellow = Material::normal(Rgb(0xFF, 0xEC, 0x27));
+        let compiled_time = world.topology().last_modification().unwrap().0;
+
+        // Edit outside of the rule
+        world.draw([(Pixel::new(14, 14), yellow)].into_iter());
+        assert!(!program.is_outdated(world.topology(), compiled_time));
+
+        // Edit inside the after frame
+        let edit_time = world.topology().last_modification().unwrap().0;
+        world.draw([(Pixel::new(12, 3), yellow)].into_iter());
+        assert!(program.is_outdated(world.topology(), edit_time));
+        assert!(program.is_outdated(world.topology(), compiled_time));
+
+        // Erase the whole rule, the new transparent region is not contained in the rule bounds
+        let mut world = World::load("test_resources/compiler/basic_1/world.png").unwrap();
+        let rule_bounds = program.rules[0].source.as_ref().unwrap().bounds;
+        world.draw(
+            rule_bounds
+                .iter_half_open()
+                .map(|point| (Pixel::new(point.x, point.y), Material::TRANSPARENT)),
+        );
+        assert!(program.is_outdated(world.topology(), compiled_time));
+    }
 }
@@ -1,9 +1,10 @@
 use crate::{
     material::Material,
     math::{point::Point, rect::Rect, rgba8::Rgba8},
+    png_metadata::PngMetadata,
 };
 use data_encoding::BASE64;
-use image::ImageEncoder;
+use log::warn;
 use std::{
     io::Cursor,
     ops::{Index, IndexMut},
@@ -303,30 +304,60 @@ impl RgbaField {
         Ok(())
     }
 
-    /// Uses more aggressive compression than save()
-    pub fn save_png(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
-        use image::{
-            ExtendedColorType,
-            codecs::png::{CompressionType, FilterType, PngEncoder},
-        };
-
-        let file = std::fs::File::create(path)?;
-        let writer = std::io::BufWriter::new(file);
-
-        // Create PngEncoder with best compression
-        let encoder =
-            PngEncoder::new_with_quality(writer, CompressionType::Best, FilterType::Adaptive);
+    /// Uses more aggressive compression than save() and stores `metadata` in text chunks
+    pub fn save_png(&self, path: impl AsRef<Path>, metadata: &PngMetadata) -> anyhow::Result<()> {
+        std::fs::write(path, self.to_png_with_metadata(metadata)?)?;
+        Ok(())
+    }
 
-        // Encode the image buffer
-        let bytes = self.as_raw();
-        encoder.write_image(
-            bytes,
-            self.width() as u32,
-            self.height() as u32,
-            ExtendedColorType::Rgba8,
-        )?;
+    pub fn to_png_with_metadata(&self, metadata: &PngMetadata) -> anyhow::Result<Vec<u8>> {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, self.width() as u32, self.height() as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_compression(png::Compression::Best);
+        encoder.set_adaptive_filter(png::AdaptiveFilterType::Adaptive);
+        metadata.write(&mut encoder)?;
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(self.as_raw())?;
+        writer.finish()?;
+        Ok(png)
+    }
+
+    /// Like load_from_memory(), also reads the metadata if the image is a png. The png is only
+    /// decoded once. Invalid metadata is ignored with a warning, it shouldn't prevent loading the
+    /// image.
+    pub fn load_with_metadata(memory: &[u8]) -> anyhow::Result<(Self, PngMetadata)> {
+        let mut decoder = png::Decoder::new(Cursor::new(memory));
+        decoder.set_ignore_text_chunk(false);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let Ok(mut reader) = decoder.read_info() else {
+            // Not a png, gifs for example have no metadata
+            return Ok((Self::load_from_memory(memory)?, PngMetadata::default()));
+        };
 
-        Ok(())
+        let metadata = PngMetadata::from_info(reader.info()).unwrap_or_else(|err| {
+            warn!("Ignoring png metadata with error {err}");
+            PngMetadata::default()
+        });
+
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let frame = reader.next_frame(&mut buffer)?;
+        let bytes = &buffer[..frame.buffer_size()];
+        let channels = frame.color_type.samples();
+        let size = Point(frame.width as i64, frame.height as i64);
+        let mut rgba_field = Self::filled(Rect::low_size(Point::ZERO, size), Rgba8::ZERO);
+        for (rgba, pixel) in rgba_field.iter_mut().zip(bytes.chunks_exact(channels)) {
+            *rgba = match *pixel {
+                [gray] => Rgba8::new(gray, gray, gray, 255),
+                [gray, alpha] => Rgba8::new(gray, gray, gray, alpha),
+                [r, g, b] => Rgba8::new(r, g, b, 255),
+                [r, g, b, a] => Rgba8::new(r, g, b, a),
+                _ => unreachable!("normalize_to_color8 gives 8 bit samples"),
+            };
+        }
+        Ok((rgba_field, metadata))
     }
 
     pub fn to_png(&self) -> anyhow::Result<Vec<u8>> {
@@ -0,0 +1,457 @@
+//! Structured exports of a `Topology` and of a `Morphism` between two topologies, as JSON for
+//! tools and tests and as Graphviz DOT for looking at them. Elements are named by their position
+//! in the topology: region `r2` is the third region in key order, `r2b1` its first hole and
+//! `r2b1s0` the first atomic seam of that hole.
+
+use crate::{
+    material::Material,
+    math::{
+        pixel::{Corner, Side},
+        rect::Rect,
+    },
+    morphism::Morphism,
+    topology::{BorderKey, RegionKey, Seam, Topology},
+};
+use itertools::Itertools;
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter, Write},
+};
+
+/// Minimal JSON value, objects keep the order of their keys.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn object<'a>(entries: impl IntoIterator<Item = (&'a str, Json)>) -> Self {
+        Self::Object(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    fn string(value: impl Into<String>) -> Self {
+        Self::String(value.into())
+    }
+
+    /// Value of `key` if `self` is an object
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Self::Object(entries) => entries
+                .iter()
+                .find(|(entry_key, _)| entry_key == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Self::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Self::Null, Into::into)
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<i64> for Json {
+    fn from(value: i64) -> Self {
+        Self::Number(value)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Self {
+        Self::Number(value as i64)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Null => write!(f, "null"),
+            Self::Bool(value) => write!(f, "{value}"),
+            Self::Number(value) => write!(f, "{value}"),
+            Self::String(value) => write_json_string(f, value),
+            Self::Array(items) => write!(f, "[{}]", items.iter().join(",")),
+            Self::Object(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_json_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_json_string(f: &mut Formatter<'_>, value: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    write!(f, "\"")
+}
+
+/// Names of the elements of a topology, see module documentation.
+struct Names<'a> {
+    topology: &'a Topology,
+    region_indices: BTreeMap<RegionKey, usize>,
+}
+
+impl<'a> Names<'a> {
+    fn new(topology: &'a Topology) -> Self {
+        let region_indices = topology
+            .iter_region_keys()
+            .enumerate()
+            .map(|(i, region_key)| (region_key, i))
+            .collect();
+        Self {
+            topology,
+            region_indices,
+        }
+    }
+
+    fn region(&self, region_key: RegionKey) -> String {
+        format!("r{}", self.region_indices[&region_key])
+    }
+
+    fn border(&self, border_key: BorderKey) -> String {
+        format!(
+            "{}b{}",
+            self.region(border_key.region_key),
+            border_key.i_border
+        )
+    }
+
+    /// Seams that are not atomic are named after their first atomic seam.
+    fn seam(&self, seam: Seam) -> String {
+        let index = self.topology.seam_indices[&seam.start];
+        format!("{}s{}", self.border(index.border_index()), index.i_seam)
+    }
+}
+
+fn rect_json(rect: Rect<i64>) -> Json {
+    Json::object([
+        ("x", rect.left().into()),
+        ("y", rect.top().into()),
+        ("width", rect.width().into()),
+        ("height", rect.height().into()),
+    ])
+}
+
+fn material_json(material: Material) -> Json {
+    Json::object([
+        ("class", Json::string(format!("{:?}", material.class))),
+        ("rgb", Json::string(rgb_hex(material))),
+    ])
+}
+
+fn side_json(side: Side) -> Json {
+    Json::object([
+        ("x", side.left_pixel.x.into()),
+        ("y", side.left_pixel.y.into()),
+        ("name", Json::string(format!("{:?}", side.name))),
+    ])
+}
+
+fn corner_json(corner: Corner) -> Json {
+    Json::object([
+        ("x", corner.pixel.x.into()),
+        ("y", corner.pixel.y.into()),
+        ("name", Json::string(format!("{:?}", corner.name))),
+    ])
+}
+
+fn seam_json(names: &Names, seam: Seam) -> Json {
+    Json::object([
+        ("id", names.seam(seam).into()),
+        ("start", side_json(seam.start)),
+        ("stop", side_json(seam.stop)),
+        ("atoms", seam.atoms.into()),
+    ])
+}
+
+fn rgb_hex(material: Material) -> String {
+    let rgb = material.rgb;
+    format!("#{:02x}{:02x}{:02x}", rgb.r, rgb.g, rgb.b)
+}
+
+/// Regions with their material, bounds, containing region and borders, and the seams of each
+/// border with the region on their right (`null` for void).
+pub fn topology_json(topology: &Topology) -> Json {
+    let names = Names::new(topology);
+    let regions = topology.iter_regions().map(|(region_key, region)| {
+        let borders = region
+            .boundary
+            .borders
+            .iter()
+            .enumerate()
+            .map(|(i_border, border)| {
+                let seams = border.atomic_seams().map(|seam| {
+                    let right = topology.right_of(seam).map(|right| names.region(right));
+                    Json::object([
+                        ("id", names.seam(seam).into()),
+                        ("start", side_json(seam.start)),
+                        ("stop", side_json(seam.stop)),
+                        ("right_region", right.into()),
+                    ])
+                });
+                Json::object([
+                    (
+                        "id",
+                        names.border(BorderKey::new(region_key, i_border)).into(),
+                    ),
+                    ("outer", border.is_outer.into()),
+                    ("seams", Json::Array(seams.collect())),
+                ])
+            });
+        let containing = topology
+            .containing_region(region_key)
+            .map(|containing| names.region(containing));
+        Json::object([
+            ("id", names.region(region_key).into()),
+            ("material", material_json(region.material)),
+            ("bounds", rect_json(region.bounds())),
+            ("containing_region", containing.into()),
+            ("borders", Json::Array(borders.collect())),
+        ])
+    });
+
+    Json::object([
+        ("bounds", rect_json(topology.bounding_rect())),
+        ("regions", Json::Array(regions.collect())),
+    ])
+}
+
+/// Pattern element to world element for every region, border, seam and corner of the morphism.
+pub fn morphism_json(phi: &Morphism, pattern: &Topology, world: &Topology) -> Json {
+    let pattern_names = Names::new(pattern);
+    let world_names = Names::new(world);
+    let pair = |pattern: Json, world: Json| Json::object([("pattern", pattern), ("world", world)]);
+
+    let regions = phi.region_map.iter().map(|(&region_key, &phi_region_key)| {
+        pair(
+            pattern_names.region(region_key).into(),
+            world_names.region(phi_region_key).into(),
+        )
+    });
+    let borders = phi.border_map.iter().map(|(&border_key, &phi_border_key)| {
+        pair(
+            pattern_names.border(border_key).into(),
+            world_names.border(phi_border_key).into(),
+        )
+    });
+    let seams = phi.seam_map.iter().map(|(&seam, &phi_seam)| {
+        pair(
+            seam_json(&pattern_names, seam),
+            seam_json(&world_names, phi_seam),
+        )
+    });
+    let corners = phi
+        .corner_map
+        .iter()
+        .map(|(&corner, &phi_corner)| pair(corner_json(corner), corner_json(phi_corner)));
+
+    Json::object([
+        ("regions", Json::Array(regions.collect())),
+        ("borders", Json::Array(borders.collect())),
+        ("seams", Json::Array(seams.collect())),
+        ("corners", Json::Array(corners.collect())),
+    ])
+}
+
+/// Regions as nodes filled with their material color. Neighboring regions are connected by an
+/// undirected edge labelled with the number of atomic seams between them, the containment tree
+/// is drawn as dashed arrows from the containing region.
+pub fn topology_dot(topology: &Topology) -> String {
+    let mut dot = String::new();
+    writeln!(dot, "digraph topology {{").unwrap();
+    writeln!(dot, "    node [shape=box style=filled]").unwrap();
+    write_topology_dot(&mut dot, topology, "", "    ");
+    writeln!(dot, "}}").unwrap();
+    dot
+}
+
+/// The pattern and the world side by side, with a dotted arrow from each pattern region to its
+/// image in the world.
+pub fn morphism_dot(phi: &Morphism, pattern: &Topology, world: &Topology) -> String {
+    let pattern_names = Names::new(pattern);
+    let world_names = Names::new(world);
+
+    let mut dot = String::new();
+    writeln!(dot, "digraph morphism {{").unwrap();
+    writeln!(dot, "    node [shape=box style=filled]").unwrap();
+    for (name, prefix, topology) in [("pattern", "p_", pattern), ("world", "w_", world)] {
+        writeln!(dot, "    subgraph cluster_{name} {{").unwrap();
+        writeln!(dot, "        label=\"{name}\"").unwrap();
+        write_topology_dot(&mut dot, topology, prefix, "        ");
+        writeln!(dot, "    }}").unwrap();
+    }
+    for (&region_key, &phi_region_key) in &phi.region_map {
+        writeln!(
+            dot,
+            "    p_{} -> w_{} [style=dotted color=blue constraint=false]",
+            pattern_names.region(region_key),
+            world_names.region(phi_region_key)
+        )
+        .unwrap();
+    }
+    writeln!(dot, "}}").unwrap();
+    dot
+}
+
+fn write_topology_dot(dot: &mut String, topology: &Topology, prefix: &str, indent: &str) {
+    let names = Names::new(topology);
+    let void = format!("{prefix}void");
+
+    for (region_key, region) in topology.iter_regions() {
+        let name = names.region(region_key);
+        writeln!(
+            dot,
+            "{indent}{prefix}{name} [label=\"{name}\\n{:?}\" fillcolor=\"{}\"]",
+            region.material.class,
+            rgb_hex(region.material)
+        )
+        .unwrap();
+    }
+
+    // Each seam between two regions is contained in both regions, count it once.
+    let mut neighbors: BTreeMap<(String, String), usize> = BTreeMap::new();
+    for seam in topology.iter_seams() {
+        let left = format!("{prefix}{}", names.region(topology.left_of(seam)));
+        match topology.right_of(seam) {
+            Some(right) => {
+                let right = format!("{prefix}{}", names.region(right));
+                if left < right {
+                    *neighbors.entry((left, right)).or_default() += 1;
+                }
+            }
+            None => *neighbors.entry((left, void.clone())).or_default() += 1,
+        }
+    }
+
+    if neighbors.keys().any(|(_, right)| right == &void) {
+        writeln!(dot, "{indent}{void} [label=\"void\" style=dashed]").unwrap();
+    }
+    for ((left, right), seams) in &neighbors {
+        writeln!(
+            dot,
+            "{indent}{left} -> {right} [dir=none label=\"{seams}\"]"
+        )
+        .unwrap();
+    }
+
+    for region_key in topology.iter_region_keys() {
+        if let Some(containing) = topology.containing_region(region_key) {
+            writeln!(
+                dot,
+                "{indent}{prefix}{} -> {prefix}{} [style=dashed]",
+                names.region(containing),
+                names.region(region_key)
+            )
+            .unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        graph_export::{morphism_dot, morphism_json, topology_dot, topology_json},
+        morphism::{Morphism, test::seam_map_from_colors},
+        topology::Topology,
+    };
+
+    #[test]
+    fn topology() {
+        let topology = Topology::load("test_resources/topology/3b.png").unwrap();
+        let json = topology_json(&topology);
+        let regions = json.get("regions").unwrap().as_array().unwrap();
+        assert_eq!(regions.len(), topology.regions.len());
+
+        // Each region is contained in the region named in its json
+        for (region_key, region_json) in topology.iter_region_keys().zip(regions) {
+            let containing = region_json.get("containing_region").unwrap().as_str();
+            let expected = topology.containing_region(region_key).map(|containing| {
+                topology
+                    .iter_region_keys()
+                    .position(|key| key == containing)
+            });
+            let expected = expected.map(|i| format!("r{}", i.unwrap()));
+            assert_eq!(containing, expected.as_deref());
+        }
+
+        let text = json.to_string();
+        assert!(text.starts_with("{\"bounds\":"));
+        assert_eq!(
+            text.matches("\"right_region\"").count(),
+            topology.iter_seams().count()
+        );
+
+        let dot = topology_dot(&topology);
+        assert!(dot.starts_with("digraph topology {"));
+        assert!(dot.trim_end().ends_with('}'));
+    }
+
+    #[test]
+    fn morphism() {
+        let folder = "test_resources/morphism";
+        let dom = Topology::load(format!("{folder}/a.png")).unwrap();
+        let codom = Topology::load(format!("{folder}/phi_a.png")).unwrap();
+        let seam_phi = seam_map_from_colors(&dom, &codom).unwrap();
+        let phi = Morphism::induced_from_seam_map(&dom, &codom, seam_phi).unwrap();
+
+        let json = morphism_json(&phi, &dom, &codom);
+        let regions = json.get("regions").unwrap().as_array().unwrap();
+        assert_eq!(regions.len(), dom.regions.len());
+        let seams = json.get("seams").unwrap().as_array().unwrap();
+        assert_eq!(seams.len(), phi.seam_map.len());
+
+        let dot = morphism_dot(&phi, &dom, &codom);
+        assert_eq!(dot.matches("style=dotted").count(), phi.region_map.len());
+    }
+}
@@ -98,6 +98,22 @@ impl Snapshot {
     }
 }
 
+/// Contents of a `Snapshot` without the link to its parent, for saving
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavedSnapshot {
+    pub material_map: MaterialMap,
+    pub selection: Option<MaterialMap>,
+    pub cause: SnapshotCause,
+}
+
+/// The snapshots of a `History` from the root up to the active snapshot. The snapshots after
+/// `head` can be redone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavedHistory {
+    pub snapshots: Vec<SavedSnapshot>,
+    pub head: usize,
+}
+
 pub struct History {
     /// Currently active node
     pub head: Rc<Snapshot>,
@@ -143,6 +159,61 @@ impl History {
         self.active = self.head.clone();
     }
 
+    pub fn to_saved(&self) -> SavedHistory {
+        let path = self.active.path_to(&self.root).unwrap();
+        let i_head = path
+            .iter()
+            .position(|&snapshot| Rc::ptr_eq(snapshot, &self.head))
+            .unwrap();
+        let snapshots = path
+            .iter()
+            .rev()
+            .map(|snapshot| SavedSnapshot {
+                material_map: snapshot.material_map.clone(),
+                selection: snapshot
+                    .selection
+                    .as_ref()
+                    .map(|selection| selection.material_map().clone()),
+                cause: snapshot.cause,
+            })
+            .collect();
+
+        SavedHistory {
+            snapshots,
+            head: path.len() - 1 - i_head,
+        }
+    }
+
+    /// `saved` has to contain at least one snapshot and `saved.head` has to be one of them.
+    pub fn from_saved(saved: SavedHistory) -> Self {
+        assert!(saved.head < saved.snapshots.len());
+        let mut head = None;
+        let mut active: Option<Rc<Snapshot>> = None;
+        for (i, saved_snapshot) in saved.snapshots.into_iter().enumerate() {
+            let snapshot = Rc::new(Snapshot::new(
+                saved_snapshot.material_map,
+                saved_snapshot.selection.map(Selection::new),
+                saved_snapshot.cause,
+                active.take(),
+            ));
+            if i == saved.head {
+                head = Some(snapshot.clone());
+            }
+            active = Some(snapshot);
+        }
+
+        let active = active.unwrap();
+        let mut root = active.clone();
+        while let Some(parent) = root.parent.clone() {
+            root = parent;
+        }
+        Self {
+            head: head.unwrap(),
+            active,
+            root,
+        }
+    }
+
     pub fn undo(&mut self) {
         if let Some(parent) = &self.head.parent {
             self.head = parent.clone();
@@ -0,0 +1,247 @@
+//! Import of images that were not saved by topolang, for example screenshots or drawings from
+//! other editors. Their alpha values usually don't encode a material class, so instead of
+//! panicking like `Material::from` the import maps or rejects them and summarizes what it did.
+
+use crate::{
+    field::RgbaField,
+    material::Material,
+    math::rgba8::{Rgb8, Rgba8},
+    palettes::Palette,
+    pixmap::MaterialMap,
+    utils::ReflectEnum,
+};
+use anyhow::bail;
+use itertools::Itertools;
+use std::collections::BTreeMap;
+
+/// What to do with pixels whose alpha doesn't encode a material class
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownAlpha {
+    /// Opaque enough pixels become normal, the others transparent. The other classes are encoded
+    /// by exact alpha values that other editors don't produce by accident, so they are never the
+    /// nearest class.
+    Nearest,
+
+    /// Fail the import and report the alpha values
+    Reject,
+}
+
+impl UnknownAlpha {
+    pub const ALL: [Self; 2] = [Self::Nearest, Self::Reject];
+
+    fn nearest(rgba: Rgba8) -> Material {
+        if rgba.a >= 128 {
+            Material::normal(rgba.rgb())
+        } else {
+            Material::TRANSPARENT
+        }
+    }
+}
+
+impl ReflectEnum for UnknownAlpha {
+    fn all() -> &'static [Self] {
+        &Self::ALL
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Nearest => "Nearest",
+            Self::Reject => "Reject",
+        }
+    }
+}
+
+/// What the import converted, empty if the image was a topolang image already.
+#[derive(Debug, Clone, Default)]
+pub struct ImportSummary {
+    /// Pixel count by unknown alpha value
+    pub unknown_alphas: BTreeMap<u8, usize>,
+
+    /// Pixels with an unknown alpha that became normal
+    pub to_normal: usize,
+
+    /// Pixels with an unknown alpha that became transparent
+    pub to_transparent: usize,
+
+    /// Distinct colors that were replaced by a palette color, and the palette.
+    pub quantized: Option<(usize, String)>,
+}
+
+impl ImportSummary {
+    pub fn is_lossless(&self) -> bool {
+        self.unknown_alphas.is_empty() && self.quantized.is_none()
+    }
+
+    pub fn message(&self) -> String {
+        let mut lines = Vec::new();
+        if !self.unknown_alphas.is_empty() {
+            lines.push(format!(
+                "{} pixels with unknown alpha {}: {} became normal, {} transparent",
+                self.unknown_alphas.values().sum::<usize>(),
+                self.alphas(),
+                self.to_normal,
+                self.to_transparent
+            ));
+        }
+        if let Some((colors, palette)) = &self.quantized {
+            lines.push(format!("Quantized {colors} colors to palette {palette}"));
+        }
+        lines.join("\n")
+    }
+
+    /// Unknown alpha values, only the most frequent ones if there are many
+    fn alphas(&self) -> String {
+        const MAX_ALPHAS: usize = 8;
+        let alphas = self
+            .unknown_alphas
+            .iter()
+            .sorted_by_key(|&(&alpha, &count)| (std::cmp::Reverse(count), alpha))
+            .map(|(alpha, _)| alpha)
+            .take(MAX_ALPHAS)
+            .sorted()
+            .join(", ");
+        if self.unknown_alphas.len() > MAX_ALPHAS {
+            format!("({alphas}, ...)")
+        } else {
+            format!("({alphas})")
+        }
+    }
+}
+
+/// Convert an image of any origin to materials. Colors of normal, solid, sleeping and special
+/// materials are replaced by the nearest color of `palette`, if any.
+pub fn import_image(
+    rgba_field: &RgbaField,
+    unknown_alpha: UnknownAlpha,
+    palette: Option<&Palette>,
+) -> anyhow::Result<(MaterialMap, ImportSummary)> {
+    let mut summary = ImportSummary::default();
+    let mut quantized_colors: BTreeMap<Rgb8, Rgb8> = BTreeMap::new();
+
+    let material_field = rgba_field.clone().into_map(|rgba| {
+        let mut material = Material::try_from_rgba(rgba).unwrap_or_else(|| {
+            *summary.unknown_alphas.entry(rgba.a).or_default() += 1;
+            let material = UnknownAlpha::nearest(rgba);
+            if material.is_normal() {
+                summary.to_normal += 1;
+            } else {
+                summary.to_transparent += 1;
+            }
+            material
+        });
+
+        if let (Some(palette), true) = (palette, is_quantized(material)) {
+            let rgb = *quantized_colors
+                .entry(material.rgb)
+                .or_insert_with(|| nearest_color(palette, material.rgb));
+            material.rgb = rgb;
+        }
+        material
+    });
+
+    if unknown_alpha == UnknownAlpha::Reject && !summary.unknown_alphas.is_empty() {
+        bail!(
+            "Image has {} pixels with unknown alpha {}",
+            summary.unknown_alphas.values().sum::<usize>(),
+            summary.alphas()
+        );
+    }
+
+    if let Some(palette) = palette {
+        let changed = quantized_colors
+            .iter()
+            .filter(|(rgb, quantized)| rgb != quantized)
+            .count();
+        if changed > 0 {
+            summary.quantized = Some((changed, palette.name.clone()));
+        }
+    }
+
+    Ok((MaterialMap::from(material_field), summary))
+}
+
+/// Rule and wildcard colors have a meaning and transparent has no color.
+fn is_quantized(material: Material) -> bool {
+    !material.is_rule() && !material.is_wildcard() && material != Material::TRANSPARENT
+}
+
+fn nearest_color(palette: &Palette, rgb: Rgb8) -> Rgb8 {
+    let distance = |other: Rgb8| {
+        let [r, g, b] = [(rgb.r, other.r), (rgb.g, other.g), (rgb.b, other.b)]
+            .map(|(a, b)| (a as i32 - b as i32).pow(2));
+        r + g + b
+    };
+    palette
+        .colors
+        .iter()
+        .map(|color| color.rgb())
+        .min_by_key(|&color| distance(color))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        field::RgbaField,
+        image_import::{UnknownAlpha, import_image},
+        material::Material,
+        math::{point::Point, rect::Rect, rgba8::Rgba8},
+        palettes::Palette,
+    };
+
+    #[test]
+    fn unknown_alphas() {
+        let rgba_field = RgbaField::from_linear(
+            Rect::low_size(Point(0, 0), Point(4, 1)),
+            vec![
+                Rgba8::new(10, 20, 30, 255),
+                Rgba8::new(10, 20, 30, 200),
+                Rgba8::new(10, 20, 30, 17),
+                // Rule alpha but not a rule color
+                Rgba8::new(10, 20, 30, Material::RULE_INTERIOR_ALPHA),
+            ],
+        );
+
+        let (material_map, summary) =
+            import_image(&rgba_field, UnknownAlpha::Nearest, None).unwrap();
+        let materials = material_map.field.iter().copied().collect::<Vec<_>>();
+        let normal = Material::normal(Rgba8::new(10, 20, 30, 255).rgb());
+        let transparent = Material::TRANSPARENT;
+        assert_eq!(
+            materials,
+            [
+                Some(normal),
+                Some(normal),
+                Some(transparent),
+                Some(transparent)
+            ]
+        );
+        assert_eq!(summary.unknown_alphas.len(), 3);
+        assert_eq!((summary.to_normal, summary.to_transparent), (1, 2));
+        assert!(!summary.is_lossless());
+
+        assert!(import_image(&rgba_field, UnknownAlpha::Reject, None).is_err());
+    }
+
+    #[test]
+    fn quantize() {
+        let world = RgbaField::load("test_resources/compiler/basic_1/world.png").unwrap();
+        let (_, summary) = import_image(&world, UnknownAlpha::Reject, None).unwrap();
+        assert!(summary.is_lossless());
+
+        let palette = &Palette::palettes()[0];
+        let (material_map, summary) =
+            import_image(&world, UnknownAlpha::Reject, Some(palette)).unwrap();
+        assert!(summary.quantized.is_some());
+        for material in material_map.values() {
+            if material.is_normal() {
+                assert!(
+                    palette
+                        .colors
+                        .iter()
+                        .any(|color| color.rgb() == material.rgb)
+                );
+            }
+        }
+    }
+}
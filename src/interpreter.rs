@@ -1,18 +1,36 @@
 use crate::{
-    compiler::Program,
-    rule::{CanvasInput, FillRegion, Rule, RuleApplicationContext},
-    topology::{AtomicTime, RegionKey},
-    utils::monotonic_time,
+    compiler::{GenericRule, Program},
+    rule::{CanvasInput, FillRegion, RuleApplicationContext},
+    rule_matches::RuleMatches,
+    rule_stats::RuleStats,
+    solver::plan::SearchLimits,
+    topology::{AtomicTime, MaskedTopology, RegionKey, Topology, TopologyStatistics},
+    utils::{monotonic_time, parallel_map},
     world::World,
 };
 use ahash::HashSet;
 use itertools::Itertools;
+use std::ops::Add;
 
+#[derive(Clone)]
 pub struct Interpreter {
     pub program: Program,
 
     /// Modification time that each rule has been stabilized up to (inclusive bound).
     pub cursors: Vec<AtomicTime>,
+
+    /// Profiling counters for each rule instance
+    pub stats: Vec<RuleStats>,
+
+    /// Cached matches of each rule instance, in sync with the world up to the cursor.
+    pub matches: Vec<RuleMatches>,
+
+    /// Budget of each search and of each `stabilize` call
+    pub search_limits: SearchLimits,
+
+    /// Rule applications until the search plans are compared with the material counts again, see
+    /// `replan_outdated`.
+    replan_countdown: usize,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -27,44 +45,111 @@ pub struct RuleApplication {
 pub enum StabilizeOutcome {
     Stable,
     MaxApplicationsReached,
+
+    /// A search of the generic rule with this index in `Program::rules` exceeded its budget, see
+    /// `SearchLimits`. Stabilizing again repeats the search.
+    SearchBudgetExceeded(usize),
 }
 
 impl Interpreter {
     pub fn new(program: Program) -> Self {
         let cursors = vec![-1; program.rule_instances_len()];
-        Self { program, cursors }
+        let stats = vec![RuleStats::default(); program.rule_instances_len()];
+        let matches = vec![RuleMatches::default(); program.rule_instances_len()];
+        Self {
+            program,
+            cursors,
+            stats,
+            matches,
+            search_limits: SearchLimits::default(),
+            replan_countdown: 0,
+        }
     }
 
-    /// Apply rule at any modified region after `cursor` and move `cursor` forward.
-    fn apply_rule_with_cursor(
-        world: &mut World,
-        rule: &Rule,
-        ctx: &RuleApplicationContext,
-        cursor: &mut AtomicTime,
-    ) -> bool {
-        while let Some((mtime, modified_region_key)) =
-            world.topology().first_modification_after(*cursor)
-        {
-            assert!(mtime > *cursor);
-
-            let apply_ctx = RuleApplicationContext {
-                contained: Some(modified_region_key),
-                ..*ctx
-            };
-            let modified = rule.apply(world, &apply_ctx);
-            if modified {
-                // info!("modified");
-                return true;
-            } else {
-                // info!(
-                //     "not modified, advancing cursor from {} to {}",
-                //     *cursor, mtime
-                // );
-                *cursor = mtime;
+    /// Stats summed over the instances of each generic rule, same order as `program.rules`.
+    pub fn generic_rule_stats(&self) -> Vec<RuleStats> {
+        let mut offset = 0;
+        self.program
+            .rules
+            .iter()
+            .map(|generic_rule| {
+                let len = generic_rule.instances.len();
+                let instance_stats = &self.stats[offset..offset + len];
+                offset += len;
+                instance_stats
+                    .iter()
+                    .copied()
+                    .fold(RuleStats::default(), Add::add)
+            })
+            .collect()
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats.fill(RuleStats::default());
+    }
+
+    /// Replace the program, for example after `Compiler::recompile`. Rules that were reused from
+    /// the old program keep their cursors, matches and stats, all other rules start from the
+    /// beginning.
+    pub fn set_program(&mut self, program: Program, world: &World) {
+        // Instance range of each old generic rule
+        let mut old_ranges = Vec::new();
+        let mut offset = 0;
+        for generic_rule in &self.program.rules {
+            let len = generic_rule.instances.len();
+            old_ranges.push((generic_rule, offset..offset + len));
+            offset += len;
+        }
+
+        let mut cursors = Vec::with_capacity(program.rule_instances_len());
+        let mut stats = Vec::with_capacity(program.rule_instances_len());
+        let mut matches = Vec::with_capacity(program.rule_instances_len());
+        for generic_rule in &program.rules {
+            let len = generic_rule.instances.len();
+            let reused = generic_rule.source.as_ref().and_then(|source| {
+                old_ranges.iter().find(|(old_rule, old_range)| {
+                    old_range.len() == len
+                        && old_rule
+                            .source
+                            .as_ref()
+                            .is_some_and(|old_source| old_source.is_unchanged(source))
+                })
+            });
+
+            match reused {
+                Some((_, old_range)) => {
+                    cursors.extend_from_slice(&self.cursors[old_range.clone()]);
+                    stats.extend_from_slice(&self.stats[old_range.clone()]);
+                    matches.extend_from_slice(&self.matches[old_range.clone()]);
+                }
+                None => {
+                    cursors.extend(std::iter::repeat_n(-1, len));
+                    stats.extend(std::iter::repeat_n(RuleStats::default(), len));
+                    matches.extend(std::iter::repeat_n(RuleMatches::default(), len));
+                }
+            }
+        }
+
+        // Regions that were part of a rule frame before but now belong to the world have to be
+        // visited again by all rules.
+        let revealed_mtime = self
+            .program
+            .source
+            .difference(&program.source)
+            .filter_map(|region_key| world.topology().regions.get(region_key))
+            .map(|region| region.modified_time)
+            .min();
+        if let Some(revealed_mtime) = revealed_mtime {
+            for cursor in &mut cursors {
+                *cursor = (*cursor).min(revealed_mtime - 1);
             }
         }
 
-        false
+        self.program = program;
+        self.cursors = cursors;
+        self.stats = stats;
+        self.matches = matches;
+        self.replan_countdown = 0;
     }
 
     pub fn stabilize(
@@ -72,13 +157,15 @@ impl Interpreter {
         world: &mut World,
         input: &CanvasInput,
         max_applications: usize,
+        tick_deadline: f64,
     ) -> (StabilizeOutcome, Vec<RuleApplication>) {
         let _span = tracy_client::span!("stabilize");
 
         let ctx = RuleApplicationContext {
-            contained: None,
             excluded: &self.program.source,
             input,
+            search_limits: self.search_limits,
+            tick_deadline,
         };
 
         let mut applications = Vec::new();
@@ -88,27 +175,49 @@ impl Interpreter {
                 return (StabilizeOutcome::MaxApplicationsReached, applications);
             }
 
+            if self.replan_countdown == 0 {
+                replan_outdated(&mut self.program.rules, world.topology());
+                self.replan_countdown = REPLAN_INTERVAL;
+            }
+
+            let exceeded = sync_all(
+                &self.program,
+                &mut self.cursors,
+                &mut self.stats,
+                &mut self.matches,
+                world,
+                &ctx,
+            );
+            if let Some(i_instance) = exceeded {
+                let i_rule = self.program.generic_rule_index(i_instance);
+                return (StabilizeOutcome::SearchBudgetExceeded(i_rule), applications);
+            }
+
             // Stabilize each rule
-            for ((generic_rule, rule_instance), cursor) in
-                self.program.iter_rule_instances().zip_eq(&mut self.cursors)
+            for (i_instance, ((((generic_rule, rule_instance), cursor), stats), matches)) in self
+                .program
+                .iter_rule_instances()
+                .zip_eq(&mut self.cursors)
+                .zip_eq(&mut self.stats)
+                .zip_eq(&mut self.matches)
+                .enumerate()
             {
                 let rule = &rule_instance.rule;
 
-                let modified = if !rule.before.input_conditions.is_empty() {
-                    // Modification tracking does not work when rule has input conditions. A rule
-                    // can become active even though the Topology hasn't changed.
-                    let tracy_span = tracy_client::span!("apply input rule");
-                    tracy_span.emit_text(&rule.before.debug_id_str());
-
-                    rule.apply(world, &ctx)
-                } else {
-                    let tracy_span = tracy_client::span!("apply cursor rule");
-                    tracy_span.emit_text(&rule.before.debug_id_str());
+                let tracy_span = tracy_client::span!("apply rule");
+                tracy_span.emit_text(&rule.before.debug_id_str());
 
-                    Self::apply_rule_with_cursor(world, rule, &ctx, cursor)
-                };
+                // Find new matches around the regions modified since the last time
+                if !matches.sync(rule, world, &ctx, cursor, stats) {
+                    let i_rule = self.program.generic_rule_index(i_instance);
+                    return (StabilizeOutcome::SearchBudgetExceeded(i_rule), applications);
+                }
+                let modified = matches.apply(rule, world, &ctx);
 
                 if modified {
+                    stats.applications += 1;
+                    self.replan_countdown -= 1;
+
                     // Start again
                     let application = RuleApplication {
                         real_time: monotonic_time(),
@@ -142,7 +251,9 @@ impl Interpreter {
     ) -> Ticked {
         let _span = tracy_client::span!("tick");
 
-        let (stabilize_outcome, applications) = self.stabilize(world, input, max_modifications);
+        let tick_deadline = self.search_limits.tick_deadline();
+        let (stabilize_outcome, applications) =
+            self.stabilize(world, input, max_modifications, tick_deadline);
 
         let n_woken_up = if stabilize_outcome == StabilizeOutcome::Stable {
             self.wake_up(world)
@@ -171,6 +282,83 @@ impl Ticked {
     }
 }
 
+/// Number of rule applications between two calls of `replan_outdated`. An application changes
+/// the material counts by about the size of its pattern, checking after each one is wasted work.
+const REPLAN_INTERVAL: usize = 64;
+
+/// Make new search plans for the rules whose plans were optimized for very different material
+/// counts than the world has now. Called before the first application after `set_program` and then
+/// every `REPLAN_INTERVAL` rule applications. The schedule only depends on the applications since
+/// the program was set, so the plans and therefore the order in which matches are found are the
+/// same no matter how the execution is split into ticks.
+fn replan_outdated(rules: &mut [GenericRule], topology: &Topology) {
+    let live = TopologyStatistics::new(&MaskedTopology::whole(topology));
+    for rule_instance in rules.iter_mut().flat_map(|rule| &mut rule.instances) {
+        let pattern = &mut rule_instance.rule.before;
+        if pattern.search_strategy.needs_replan(&live) {
+            pattern.search_strategy.replan(&pattern.topology, &live);
+        }
+    }
+}
+
+/// Sync all rules before applying any if the total amount of searching is above this, so that the
+/// searches can run in parallel. Otherwise each rule is synced right before it is applied.
+const SYNC_ALL_MIN_SEARCHES: usize = 256;
+
+/// Search for new matches of all rules that are behind the last modification if that requires
+/// enough searches. The searches only read the world and run in parallel on native targets.
+/// Whether they run is independent of the number of threads, so the world evolves the same way
+/// on all targets. Returns the index of the first rule instance whose search exceeded its budget.
+fn sync_all(
+    program: &Program,
+    cursors: &mut [AtomicTime],
+    stats: &mut [RuleStats],
+    matches: &mut [RuleMatches],
+    world: &World,
+    ctx: &RuleApplicationContext,
+) -> Option<usize> {
+    let topology = world.topology();
+    let (last_mtime, _) = topology.last_modification()?;
+
+    let jobs: Vec<_> = program
+        .iter_rule_instances()
+        .zip_eq(cursors)
+        .zip_eq(stats)
+        .zip_eq(matches)
+        .enumerate()
+        .filter(|(_, ((((_, rule_instance), cursor), _), _))| {
+            rule_instance.rule.before.input_conditions.is_empty() && **cursor < last_mtime
+        })
+        .collect();
+
+    let searches: usize = jobs
+        .iter()
+        .map(|(_, (((_, cursor), _), _))| {
+            if **cursor < 0 {
+                topology.regions.len()
+            } else {
+                topology.modifications_after(**cursor).count()
+            }
+        })
+        .sum();
+    if jobs.len() < 2 || searches < SYNC_ALL_MIN_SEARCHES {
+        return None;
+    }
+
+    let _span = tracy_client::span!("sync_all");
+    parallel_map(
+        jobs,
+        2,
+        |(i_instance, ((((_, rule_instance), cursor), stats), matches))| {
+            let synced = matches.sync(&rule_instance.rule, world, ctx, cursor, stats);
+            (!synced).then_some(i_instance)
+        },
+    )
+    .into_iter()
+    .flatten()
+    .next()
+}
+
 /// Wake up all sleeping regions (replace them with normal material). Returns number of regions
 /// that were woken up.
 pub fn wake_up(world: &mut World, excluded: &HashSet<RegionKey>) -> usize {
@@ -200,7 +388,11 @@ mod test {
         compiler::Compiler,
         field::RgbaField,
         interpreter::{Interpreter, StabilizeOutcome},
+        material::Material,
+        math::{point::Point, rect::Rect},
+        pixmap::MaterialMap,
         rule::CanvasInput,
+        rule_stats::RuleStats,
         world::World,
     };
 
@@ -213,7 +405,7 @@ mod test {
         let mut interpreter = Interpreter::new(rules);
 
         let (outcome, applications) =
-            interpreter.stabilize(&mut world, &CanvasInput::default(), 64);
+            interpreter.stabilize(&mut world, &CanvasInput::default(), 64, f64::INFINITY);
         assert_eq!(outcome, StabilizeOutcome::Stable);
         assert_eq!(applications.len(), expected_applications);
 
@@ -225,6 +417,116 @@ mod test {
         assert_eq!(result_pixmap, &expected_pixmap);
     }
 
+    /// Recompiling an unchanged world reuses all rules and keeps their cursors.
+    #[test]
+    fn recompile_keeps_cursors() {
+        let mut world = World::load("test_resources/compiler/b/world.png").unwrap();
+
+        let compiler = Compiler::new();
+        let program = compiler.compile(&world).unwrap();
+        let mut interpreter = Interpreter::new(program);
+        interpreter.stabilize(&mut world, &CanvasInput::default(), 64, f64::INFINITY);
+        let cursors = interpreter.cursors.clone();
+        assert!(cursors.iter().all(|&cursor| cursor >= 0));
+
+        let program = compiler.recompile(&world, &interpreter.program).unwrap();
+        interpreter.set_program(program, &world);
+        assert_eq!(interpreter.cursors, cursors);
+
+        let (outcome, applications) =
+            interpreter.stabilize(&mut world, &CanvasInput::default(), 64, f64::INFINITY);
+        assert_eq!(outcome, StabilizeOutcome::Stable);
+        assert!(applications.is_empty());
+    }
+
+    /// Editing one rule frame only recompiles that rule, the other rules keep their plans and
+    /// cursors.
+    #[test]
+    fn recompile_edited_rule() {
+        // Two copies of basic_1 on top of each other
+        let basic = MaterialMap::load("test_resources/compiler/basic_1/world.png").unwrap();
+        let mut material_map = MaterialMap::filled(
+            Rect::low_size(Point(0, 0), Point(16, 32)),
+            Material::TRANSPARENT,
+        );
+        material_map.blit(&basic);
+        material_map.blit(&basic.translated(Point(0, 16)));
+        let mut world = World::from_material_map(material_map);
+
+        let compiler = Compiler::new();
+        let program = compiler.compile(&world).unwrap();
+        assert_eq!(program.rules.len(), 2);
+        let mut interpreter = Interpreter::new(program);
+        interpreter.stabilize(&mut world, &CanvasInput::default(), 64, f64::INFINITY);
+        let cursors = interpreter.cursors.clone();
+        assert!(cursors.iter().all(|&cursor| cursor >= 0));
+
+        // The second rule draws red instead of green
+        let after = Rect::low_size(Point(11, 19), Point(2, 2));
+        world.draw(after.iter_half_open().map(|point| (point, Material::RED)));
+        assert!(
+            interpreter
+                .program
+                .is_outdated(world.topology(), cursors[0])
+        );
+
+        let program = compiler.recompile(&world, &interpreter.program).unwrap();
+        let unchanged: Vec<_> = program
+            .rules
+            .iter()
+            .map(|rule| {
+                let source = rule.source.as_ref().unwrap();
+                interpreter.program.unchanged_rule(source).is_some()
+            })
+            .collect();
+        assert_eq!(unchanged, [true, false]);
+
+        interpreter.set_program(program, &world);
+        assert_eq!(interpreter.cursors, [cursors[0], -1]);
+
+        let (outcome, applications) =
+            interpreter.stabilize(&mut world, &CanvasInput::default(), 64, f64::INFINITY);
+        assert_eq!(outcome, StabilizeOutcome::Stable);
+        assert!(applications.is_empty());
+        assert!(
+            interpreter
+                .cursors
+                .iter()
+                .all(|&cursor| cursor >= cursors[0])
+        );
+    }
+
+    #[test]
+    fn stats() {
+        let mut world = World::load("test_resources/compiler/basic_1/world.png").unwrap();
+        let program = Compiler::new().compile(&world).unwrap();
+        let mut interpreter = Interpreter::new(program);
+        interpreter.stabilize(&mut world, &CanvasInput::default(), 64, f64::INFINITY);
+
+        let stats = interpreter.generic_rule_stats();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].applications, 1);
+        assert!(stats[0].searches >= 1);
+        assert!(stats[0].solutions >= 1);
+
+        interpreter.reset_stats();
+        assert_eq!(interpreter.generic_rule_stats()[0], RuleStats::default());
+    }
+
+    /// The search is stopped and the rule that exceeded the budget is reported.
+    #[test]
+    fn search_budget_exceeded() {
+        let mut world = World::load("test_resources/compiler/basic_1/world.png").unwrap();
+        let program = Compiler::new().compile(&world).unwrap();
+        let mut interpreter = Interpreter::new(program);
+        interpreter.search_limits.max_guesses = 0;
+
+        let (outcome, applications) =
+            interpreter.stabilize(&mut world, &CanvasInput::default(), 64, f64::INFINITY);
+        assert_eq!(outcome, StabilizeOutcome::SearchBudgetExceeded(0));
+        assert!(applications.is_empty());
+    }
+
     #[test]
     fn basic_1() {
         assert_execute_world("basic_1", 1);
@@ -0,0 +1,566 @@
+use crate::{
+    compiler::Program,
+    interpreter::{Interpreter, RuleApplication, StabilizeOutcome},
+    material::Material,
+    math::pixel::Pixel,
+    rule::CanvasInput,
+    rule_stats::RuleStats,
+    run_mode::{RunMode, RunSettings},
+    topology::AtomicTime,
+    utils::monotonic_time,
+    world::World,
+};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, mpsc},
+};
+
+/// Message from the UI to the worker. Commands that change the world carry an id so the UI knows
+/// which of its edits a snapshot already contains.
+pub enum WorkerCommand {
+    /// Replace the world, for example after undo
+    SetWorld(u64, Box<World>),
+
+    /// Pixels that were edited in the UI
+    Draw(u64, Vec<(Pixel, Material)>),
+
+    SetProgram(Program),
+    SetInput(CanvasInput),
+    SetRunSettings(RunSettings),
+    ResetStats,
+}
+
+/// Changes of the world published by the worker. The world itself is not copied, the UI applies
+/// `changes` to its own copy.
+pub struct WorkerSnapshot {
+    /// Pixels set since the previous snapshot, in the order they were set. Followed by the edits
+    /// of the UI that the worker didn't handle yet, see `InterpreterWorker::poll`.
+    pub changes: Vec<(Pixel, Material)>,
+
+    /// Id of the last command that changed the world and is contained in `changes`
+    pub edit_id: u64,
+
+    /// Rules applied since the previous snapshot
+    pub applications: Vec<RuleApplication>,
+
+    /// Stats of each rule instance, None if they belong to an outdated program
+    pub stats: Option<Vec<RuleStats>>,
+
+    /// Index of the generic rule whose search exceeded its budget, the worker paused itself.
+    pub search_budget_exceeded: Option<usize>,
+
+    /// Number of `SetProgram` commands handled before the snapshot
+    program_id: u64,
+}
+
+/// Everything the worker owns, the world and the interpreter that runs on it.
+struct WorkerState {
+    world: World,
+    interpreter: Interpreter,
+    input: CanvasInput,
+    run_settings: RunSettings,
+
+    edit_id: u64,
+    program_id: u64,
+
+    /// Rules applied since the last snapshot
+    applications: Vec<RuleApplication>,
+
+    /// Last modification of the world contained in the last snapshot
+    published_mtime: AtomicTime,
+
+    /// The world was replaced or edited since the last snapshot
+    edited: bool,
+
+    /// Sleeping regions are woken up at most once per tick
+    next_tick_time: f64,
+
+    /// Not published yet, see `WorkerSnapshot::search_budget_exceeded`
+    search_budget_exceeded: Option<usize>,
+
+    /// `monotonic_time` the searches of the current tick have to end by, None between ticks. A
+    /// tick can span several `stabilize` calls.
+    tick_deadline: Option<f64>,
+}
+
+impl WorkerState {
+    /// Modifications per `stabilize` call in run mode, the worker handles commands in between.
+    const MAX_MODIFICATIONS: usize = 32;
+
+    fn new(mut world: World, interpreter: Interpreter, run_settings: RunSettings) -> Self {
+        // The recorded edits are the changes of the next snapshot
+        world.record_edits();
+        Self {
+            world,
+            interpreter,
+            input: CanvasInput::default(),
+            run_settings,
+            edit_id: 0,
+            program_id: 0,
+            applications: Vec::new(),
+            published_mtime: -1,
+            edited: false,
+            next_tick_time: monotonic_time(),
+            search_budget_exceeded: None,
+            tick_deadline: None,
+        }
+    }
+
+    fn handle(&mut self, command: WorkerCommand) {
+        match command {
+            WorkerCommand::SetWorld(edit_id, world) => {
+                self.world = *world;
+                self.world.record_edits();
+                self.edit_id = edit_id;
+                self.edited = true;
+            }
+            WorkerCommand::Draw(edit_id, pixel_materials) => {
+                self.world.draw(pixel_materials.into_iter());
+                self.edit_id = edit_id;
+                self.edited = true;
+            }
+            WorkerCommand::SetProgram(program) => {
+                self.interpreter.set_program(program, &self.world);
+                self.program_id += 1;
+            }
+            WorkerCommand::SetInput(input) => self.input = input,
+            WorkerCommand::SetRunSettings(run_settings) => self.run_settings = run_settings,
+            WorkerCommand::ResetStats => self.interpreter.reset_stats(),
+        }
+    }
+
+    /// Start the next tick if it is due. Returns false if it is not due yet.
+    fn start_tick(&mut self, now: f64) -> bool {
+        if now < self.next_tick_time {
+            return false;
+        }
+
+        // Don't try to catch up with ticks that were missed
+        self.next_tick_time = (self.next_tick_time + self.run_settings.speed.tick_dt()).max(now);
+        true
+    }
+
+    /// Stabilize with the deadline of the current tick, a tick ends when the world is stable.
+    fn stabilize(&mut self, max_modifications: usize) -> StabilizeOutcome {
+        let search_limits = self.interpreter.search_limits;
+        let tick_deadline = *self
+            .tick_deadline
+            .get_or_insert_with(|| search_limits.tick_deadline());
+        let (outcome, applications) = self.interpreter.stabilize(
+            &mut self.world,
+            &self.input,
+            max_modifications,
+            tick_deadline,
+        );
+        self.applications.extend(applications);
+        if outcome != StabilizeOutcome::MaxApplicationsReached {
+            self.tick_deadline = None;
+        }
+        outcome
+    }
+
+    /// Run the interpreter until `deadline`. Returns the time in seconds until there is something
+    /// to do again, or None if the interpreter is still busy.
+    fn run(&mut self, deadline: f64) -> Option<f64> {
+        let _span = tracy_client::span!("WorkerState::run");
+
+        match self.run_settings.mode {
+            RunMode::Paused => Some(f64::INFINITY),
+            RunMode::Slowmo => {
+                // Only one rule application per tick
+                if self.start_tick(monotonic_time()) {
+                    // Each application is a tick of its own
+                    let outcome = self.stabilize(1);
+                    self.tick_deadline = None;
+
+                    match outcome {
+                        StabilizeOutcome::Stable => {
+                            self.interpreter.wake_up(&mut self.world);
+                        }
+                        StabilizeOutcome::SearchBudgetExceeded(i_rule) => {
+                            return Some(self.pause(i_rule));
+                        }
+                        StabilizeOutcome::MaxApplicationsReached => {}
+                    }
+                }
+                Some(self.next_tick_time - monotonic_time())
+            }
+            RunMode::Run => {
+                while monotonic_time() < deadline {
+                    match self.stabilize(Self::MAX_MODIFICATIONS) {
+                        StabilizeOutcome::Stable => {
+                            if !self.start_tick(monotonic_time()) {
+                                return Some(self.next_tick_time - monotonic_time());
+                            }
+                            self.interpreter.wake_up(&mut self.world);
+                        }
+                        StabilizeOutcome::SearchBudgetExceeded(i_rule) => {
+                            return Some(self.pause(i_rule));
+                        }
+                        StabilizeOutcome::MaxApplicationsReached => {}
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    /// Stop running because a search of rule `i_rule` exceeded its budget, it would only be
+    /// repeated. Returns the idle time.
+    fn pause(&mut self, i_rule: usize) -> f64 {
+        self.run_settings.mode = RunMode::Paused;
+        self.search_budget_exceeded = Some(i_rule);
+        f64::INFINITY
+    }
+
+    /// Put the changes of the world into `slot` if the world changed and the previous snapshot was
+    /// taken by the UI.
+    fn publish(&mut self, slot: &Mutex<Option<WorkerSnapshot>>) {
+        let mtime = self
+            .world
+            .topology()
+            .last_modification()
+            .map_or(-1, |(mtime, _)| mtime);
+        if !self.edited && mtime == self.published_mtime && self.search_budget_exceeded.is_none() {
+            return;
+        }
+
+        let mut slot = slot.lock().unwrap();
+        if slot.is_some() {
+            return;
+        }
+
+        let _span = tracy_client::span!("WorkerState::publish");
+        *slot = Some(WorkerSnapshot {
+            changes: self.world.take_edits(),
+            edit_id: self.edit_id,
+            applications: std::mem::take(&mut self.applications),
+            stats: Some(self.interpreter.stats.clone()),
+            search_budget_exceeded: self.search_budget_exceeded.take(),
+            program_id: self.program_id,
+        });
+        self.published_mtime = mtime;
+        self.edited = false;
+    }
+}
+
+/// Runs the interpreter on its own thread so expensive ticks don't block the UI. The UI sends
+/// edits, input and programs and takes the snapshots the worker publishes. Edits of the UI that
+/// are not contained in a snapshot yet are applied to it again, so they don't flicker.
+pub struct InterpreterWorker {
+    sender: mpsc::Sender<WorkerCommand>,
+    slot: Arc<Mutex<Option<WorkerSnapshot>>>,
+
+    #[cfg(not(target_arch = "wasm32"))]
+    thread: std::thread::JoinHandle<WorkerState>,
+
+    // No threads on the web, the worker runs for a part of each frame instead.
+    // TODO: Run the worker in a web worker, expensive ticks still block the UI on the web. See
+    // notes/wasm.md for what is missing.
+    #[cfg(target_arch = "wasm32")]
+    receiver: mpsc::Receiver<WorkerCommand>,
+    #[cfg(target_arch = "wasm32")]
+    state: WorkerState,
+
+    edit_id: u64,
+
+    /// Id of the last `SetWorld` command, snapshots of older worlds are dropped.
+    set_world_id: u64,
+
+    /// Edits sent to the worker that the last snapshot didn't contain yet
+    unacknowledged_edits: VecDeque<(u64, Vec<(Pixel, Material)>)>,
+
+    program_id: u64,
+    input: CanvasInput,
+    run_settings: RunSettings,
+}
+
+impl InterpreterWorker {
+    /// Longest time the worker runs before handling commands and publishing a snapshot
+    const RUN_SLICE: f64 = 1.0 / 120.0;
+
+    /// Start running `interpreter` on `world`, which must not share its `rgba_field` with the
+    /// world of the UI, see `World::snapshot`.
+    pub fn spawn(world: World, interpreter: Interpreter, run_settings: RunSettings) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let slot = Arc::new(Mutex::new(None));
+        let state = WorkerState::new(world, interpreter, run_settings);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let thread = {
+            let slot = slot.clone();
+            std::thread::Builder::new()
+                .name("interpreter".to_string())
+                .spawn(move || Self::worker_loop(state, receiver, &slot))
+                .expect("Failed to spawn interpreter thread")
+        };
+
+        Self {
+            sender,
+            slot,
+            #[cfg(not(target_arch = "wasm32"))]
+            thread,
+            #[cfg(target_arch = "wasm32")]
+            receiver,
+            #[cfg(target_arch = "wasm32")]
+            state,
+            edit_id: 0,
+            set_world_id: 0,
+            unacknowledged_edits: VecDeque::new(),
+            program_id: 0,
+            input: CanvasInput::default(),
+            run_settings,
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn worker_loop(
+        mut state: WorkerState,
+        receiver: mpsc::Receiver<WorkerCommand>,
+        slot: &Mutex<Option<WorkerSnapshot>>,
+    ) -> WorkerState {
+        tracy_client::set_thread_name!("interpreter");
+
+        let mut idle_time: Option<f64> = None;
+        loop {
+            // Sleep until the next tick unless a command arrives
+            let command = match idle_time {
+                None => receiver.try_recv().map_err(|err| match err {
+                    mpsc::TryRecvError::Empty => mpsc::RecvTimeoutError::Timeout,
+                    mpsc::TryRecvError::Disconnected => mpsc::RecvTimeoutError::Disconnected,
+                }),
+                Some(idle_time) => {
+                    let timeout = std::time::Duration::from_secs_f64(idle_time.clamp(0.0, 1.0));
+                    receiver.recv_timeout(timeout)
+                }
+            };
+
+            match command {
+                Ok(command) => state.handle(command),
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                // All commands were handled and the UI stopped the worker
+                Err(mpsc::RecvTimeoutError::Disconnected) => return state,
+            }
+            for command in receiver.try_iter() {
+                state.handle(command);
+            }
+
+            idle_time = state.run(monotonic_time() + Self::RUN_SLICE);
+            state.publish(slot);
+        }
+    }
+
+    fn send(&self, command: WorkerCommand) {
+        // The worker only stops when the sender is dropped
+        self.sender.send(command).unwrap();
+    }
+
+    /// Replace the world of the worker
+    pub fn set_world(&mut self, world: World) {
+        self.edit_id += 1;
+        self.set_world_id = self.edit_id;
+        self.unacknowledged_edits.clear();
+        self.send(WorkerCommand::SetWorld(self.edit_id, Box::new(world)));
+    }
+
+    /// Apply pixels edited in the UI to the world of the worker
+    pub fn draw(&mut self, pixel_materials: Vec<(Pixel, Material)>) {
+        if pixel_materials.is_empty() {
+            return;
+        }
+
+        self.edit_id += 1;
+        self.unacknowledged_edits
+            .push_back((self.edit_id, pixel_materials.clone()));
+        self.send(WorkerCommand::Draw(self.edit_id, pixel_materials));
+    }
+
+    pub fn set_program(&mut self, program: Program) {
+        self.program_id += 1;
+        self.send(WorkerCommand::SetProgram(program));
+    }
+
+    pub fn set_input(&mut self, input: &CanvasInput) {
+        if *input != self.input {
+            self.input = input.clone();
+            self.send(WorkerCommand::SetInput(input.clone()));
+        }
+    }
+
+    pub fn set_run_settings(&mut self, run_settings: RunSettings) {
+        if run_settings != self.run_settings {
+            self.run_settings = run_settings;
+            self.send(WorkerCommand::SetRunSettings(run_settings));
+        }
+    }
+
+    pub fn reset_stats(&self) {
+        self.send(WorkerCommand::ResetStats);
+    }
+
+    /// The latest snapshot published by the worker, followed by the edits it doesn't contain yet so
+    /// they are applied again. On the web the worker runs for `0.75 * frame_dt` first.
+    pub fn poll(&mut self, frame_dt: f64) -> Option<WorkerSnapshot> {
+        #[cfg(target_arch = "wasm32")]
+        {
+            for command in self.receiver.try_iter() {
+                self.state.handle(command);
+            }
+            self.state.run(monotonic_time() + 0.75 * frame_dt);
+            self.state.publish(&self.slot);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        let _ = frame_dt;
+
+        let mut snapshot = self.slot.lock().unwrap().take()?;
+
+        // Snapshot of a world that was replaced in the meantime
+        if snapshot.edit_id < self.set_world_id {
+            return None;
+        }
+
+        if snapshot.program_id != self.program_id {
+            snapshot.stats = None;
+        }
+
+        self.unacknowledged_edits
+            .retain(|(edit_id, _)| *edit_id > snapshot.edit_id);
+        for (_, pixel_materials) in &self.unacknowledged_edits {
+            snapshot.changes.extend_from_slice(pixel_materials);
+        }
+
+        Some(snapshot)
+    }
+
+    /// Stop the worker after it handled all commands and return its world and interpreter.
+    pub fn stop(self) -> (World, Interpreter) {
+        #[cfg(not(target_arch = "wasm32"))]
+        let state = {
+            drop(self.sender);
+            self.thread.join().expect("Interpreter thread panicked")
+        };
+
+        #[cfg(target_arch = "wasm32")]
+        let state = {
+            let mut state = self.state;
+            for command in self.receiver.try_iter() {
+                state.handle(command);
+            }
+            state
+        };
+
+        let mut world = state.world;
+        world.stop_recording_edits();
+        (world, state.interpreter)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        compiler::Compiler,
+        interpreter::{Interpreter, StabilizeOutcome},
+        interpreter_worker::{InterpreterWorker, WorkerState},
+        material::Material,
+        math::pixel::Pixel,
+        rule::CanvasInput,
+        run_mode::{RunMode, RunSettings, RunSpeed},
+        world::World,
+    };
+    use std::time::{Duration, Instant};
+
+    /// The worker runs the program to the same result as running it on the calling thread.
+    #[test]
+    fn same_as_interpreter() {
+        let world = World::load("test_resources/compiler/b/world.png").unwrap();
+        let program = Compiler::new().compile(&world).unwrap();
+
+        let mut expected_world = world.clone().snapshot();
+        let mut interpreter = Interpreter::new(program.clone());
+        let (_, expected_applications) = interpreter.stabilize(
+            &mut expected_world,
+            &CanvasInput::default(),
+            1024,
+            f64::INFINITY,
+        );
+
+        let mut worker = InterpreterWorker::spawn(
+            world.clone().snapshot(),
+            Interpreter::new(program),
+            RunSettings::new(RunMode::Run, RunSpeed::Hz1),
+        );
+
+        // Wait until the world is stable
+        let mut applications = 0;
+        let start = Instant::now();
+        while applications < expected_applications.len() && start.elapsed().as_secs() < 10 {
+            if let Some(snapshot) = worker.poll(0.0) {
+                applications += snapshot.applications.len();
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(applications, expected_applications.len());
+
+        let (world, _) = worker.stop();
+        assert!(
+            world
+                .material_map()
+                .defined_equals(expected_world.material_map())
+        );
+    }
+
+    /// A tick spans `stabilize` calls until the world is stable, they share its deadline.
+    #[test]
+    fn tick_deadline() {
+        let world = World::load("test_resources/compiler/b/world.png").unwrap();
+        let program = Compiler::new().compile(&world).unwrap();
+        let mut state = WorkerState::new(
+            world,
+            Interpreter::new(program),
+            RunSettings::new(RunMode::Run, RunSpeed::Hz60),
+        );
+
+        assert_eq!(state.stabilize(1), StabilizeOutcome::MaxApplicationsReached);
+        let tick_deadline = state.tick_deadline.unwrap();
+        assert_eq!(state.stabilize(1), StabilizeOutcome::MaxApplicationsReached);
+        assert_eq!(state.tick_deadline, Some(tick_deadline));
+
+        assert_eq!(state.stabilize(1024), StabilizeOutcome::Stable);
+        assert_eq!(state.tick_deadline, None);
+    }
+
+    /// Edits sent to the worker are applied to its world and to snapshots that don't contain them.
+    #[test]
+    fn edits() {
+        let world = World::load("test_resources/compiler/basic_1/world.png").unwrap();
+        let program = Compiler::new().compile(&world).unwrap();
+
+        let mut worker = InterpreterWorker::spawn(
+            world.clone().snapshot(),
+            Interpreter::new(program),
+            RunSettings::new(RunMode::Paused, RunSpeed::Hz60),
+        );
+
+        let pixel = Pixel::new(0, 0);
+        worker.draw(vec![(pixel, Material::TRANSPARENT)]);
+
+        // Either the worker already applied the edit or it is applied again
+        let start = Instant::now();
+        let snapshot = loop {
+            if let Some(snapshot) = worker.poll(0.0) {
+                break snapshot;
+            }
+            assert!(start.elapsed().as_secs() < 10);
+            std::thread::sleep(Duration::from_millis(1));
+        };
+        let mut snapshot_world = world.clone();
+        snapshot_world.draw(snapshot.changes.into_iter());
+        assert_eq!(
+            snapshot_world.material_map().get(pixel),
+            Some(Material::TRANSPARENT)
+        );
+
+        let (world, _) = worker.stop();
+        assert_eq!(world.material_map().get(pixel), Some(Material::TRANSPARENT));
+    }
+}
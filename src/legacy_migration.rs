@@ -0,0 +1,152 @@
+//! Rewrites worlds that still use deprecated alpha encodings, like the legacy solid alpha 170 or
+//! the legacy rule alphas 180 and 111. Migrated files are encoded like the editor saves them, so
+//! once all worlds are migrated the legacy branches of `Material::try_from_rgba` can go.
+
+use crate::{
+    field::RgbaField, material::Material, material_effects::material_map_effects,
+    math::rgba8::Rgba8, pixmap::MaterialMap, png_metadata::PngMetadata,
+};
+use anyhow::{Context, bail};
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+#[allow(deprecated)]
+pub const LEGACY_ALPHAS: [u8; 3] = [
+    Material::LEGACY_SOLID_ALPHA,
+    Material::LEGACY_RULE_ALPHA,
+    Material::LEGACY_RULE_ALPHA_2,
+];
+
+/// Number of pixels of each legacy alpha in a file
+pub type LegacyPixels = BTreeMap<u8, usize>;
+
+pub fn legacy_pixels(rgba_field: &RgbaField) -> LegacyPixels {
+    let mut legacy_pixels = LegacyPixels::new();
+    for rgba in rgba_field.iter() {
+        if LEGACY_ALPHAS.contains(&rgba.a) {
+            *legacy_pixels.entry(rgba.a).or_default() += 1;
+        }
+    }
+    legacy_pixels
+}
+
+/// Human readable list like `12 legacy solid (170), 3 legacy rule (180)`
+#[allow(deprecated)]
+pub fn describe(legacy_pixels: &LegacyPixels) -> String {
+    let descriptions: Vec<_> = legacy_pixels
+        .iter()
+        .map(|(&alpha, count)| {
+            let kind = if alpha == Material::LEGACY_SOLID_ALPHA {
+                "solid"
+            } else {
+                "rule"
+            };
+            format!("{count} legacy {kind} ({alpha})")
+        })
+        .collect();
+    descriptions.join(", ")
+}
+
+/// All pngs in `folder` and its subfolders, sorted.
+pub fn world_files(folder: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut folders = vec![folder.to_path_buf()];
+    while let Some(folder) = folders.pop() {
+        let entries =
+            std::fs::read_dir(&folder).with_context(|| format!("Failed to read {folder:?}"))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_dir() {
+                folders.push(path);
+            } else if path.extension().map(OsStr::to_ascii_lowercase).as_deref()
+                == Some(OsStr::new("png"))
+            {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Find the legacy pixels of the world at `path` and rewrite it unless `dry_run`. The metadata of
+/// the png is kept. Fails without writing if the file contains alphas that encode no material, for
+/// example because it is an icon and not a world.
+pub fn migrate_file(path: &Path, dry_run: bool) -> anyhow::Result<LegacyPixels> {
+    let content = std::fs::read(path)?;
+    let rgba_field = RgbaField::load_from_memory(&content)?;
+    let legacy_pixels = legacy_pixels(&rgba_field);
+    if legacy_pixels.is_empty() {
+        return Ok(legacy_pixels);
+    }
+
+    if let Some(rgba) = rgba_field
+        .iter()
+        .find(|&&rgba| Material::try_from_rgba(rgba).is_none())
+    {
+        bail!("Not a world, {} encodes no material", rgba.hex());
+    }
+    if dry_run {
+        return Ok(legacy_pixels);
+    }
+
+    let material_map = MaterialMap::from(&rgba_field);
+    let migrated = material_map_effects(&material_map, Rgba8::TRANSPARENT);
+    // Should never happen, would mean the effects don't round trip
+    if MaterialMap::from(&migrated) != material_map {
+        bail!("Migrated world has different materials");
+    }
+
+    let metadata = PngMetadata::read(&content)?;
+    migrated.save_png(path, &metadata)?;
+    Ok(legacy_pixels)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        field::RgbaField,
+        legacy_migration::{legacy_pixels, migrate_file, world_files},
+        material::Material,
+        math::{point::Point, rect::Rect, rgba8::Rgba8},
+        pixmap::MaterialMap,
+        png_metadata::PngMetadata,
+    };
+
+    #[test]
+    #[allow(deprecated)]
+    fn migrate() {
+        let before = Material::RULE_BEFORE_RGB;
+        let rgba_field = RgbaField::from_linear(
+            Rect::low_size(Point(0, 0), Point(2, 2)),
+            vec![
+                Rgba8::from_rgb_a(before, Material::LEGACY_RULE_ALPHA),
+                Rgba8::from_rgb_a(before, Material::LEGACY_RULE_ALPHA_2),
+                Rgba8::new(10, 20, 30, Material::LEGACY_SOLID_ALPHA),
+                Rgba8::new(10, 20, 30, 255),
+            ],
+        );
+        let folder = std::env::temp_dir().join("topolang_legacy_migration");
+        std::fs::create_dir_all(folder.join("sub")).unwrap();
+        let path = folder.join("sub/legacy.png");
+        rgba_field.save_png(&path, &PngMetadata::default()).unwrap();
+
+        assert_eq!(world_files(&folder).unwrap(), [path.as_path()]);
+
+        // Dry run reports but doesn't write
+        let legacy = migrate_file(&path, true).unwrap();
+        assert_eq!(legacy.values().sum::<usize>(), 3);
+        assert_eq!(RgbaField::load(&path).unwrap(), rgba_field);
+
+        let legacy = migrate_file(&path, false).unwrap();
+        assert_eq!(legacy.len(), 3);
+        let migrated = RgbaField::load(&path).unwrap();
+        assert!(legacy_pixels(&migrated).is_empty());
+        assert_eq!(MaterialMap::from(&migrated), MaterialMap::from(&rgba_field));
+
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+}
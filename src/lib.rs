@@ -10,17 +10,24 @@ use wasm_bindgen::JsCast;
 use wasm_bindgen::prelude::wasm_bindgen;
 
 pub mod app;
+pub(crate) mod aseprite;
 pub mod benchmarks;
 pub(crate) mod brush;
 pub(crate) mod camera;
+pub mod cli;
 pub(crate) mod compiler;
 pub(crate) mod coordinate_frame;
 pub(crate) mod cycle_segments;
 pub(crate) mod demos;
 pub(crate) mod field;
+pub(crate) mod graph_export;
 pub(crate) mod history;
+pub(crate) mod image_import;
 pub(crate) mod interpreter;
+pub(crate) mod interpreter_worker;
+pub(crate) mod legacy_migration;
 pub(crate) mod line_drawing;
+pub(crate) mod lints;
 pub(crate) mod material;
 pub(crate) mod material_effects;
 pub(crate) mod math;
@@ -29,11 +36,18 @@ pub(crate) mod new_regions;
 pub(crate) mod painting;
 pub(crate) mod palettes;
 pub(crate) mod pixmap;
+pub(crate) mod png_metadata;
+pub(crate) mod project;
 pub(crate) mod regions;
 pub(crate) mod rule;
 pub(crate) mod rule_activity;
+pub(crate) mod rule_matches;
+pub(crate) mod rule_stats;
 pub(crate) mod run_mode;
+pub(crate) mod share_link;
 pub(crate) mod solver;
+pub(crate) mod svg_export;
+pub(crate) mod system_clipboard;
 pub(crate) mod topology;
 pub(crate) mod utils;
 pub(crate) mod view;
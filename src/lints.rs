@@ -0,0 +1,341 @@
+use crate::{
+    compiler::{GenericRule, Program, RuleInstance},
+    material::Material,
+    math::{rect::Rect, rgba8::Rgb8},
+    world::World,
+};
+use ahash::HashSet;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintKind {
+    /// The before pattern uses a material that exists nowhere in the world and is not created by
+    /// any rule.
+    UnreachableRule,
+
+    /// The rule neither fills nor draws anything.
+    NoopRule,
+
+    /// An earlier rule has the identical before pattern and is always applied first.
+    ShadowedRule,
+
+    /// A choice list that is not used by any placeholder.
+    UnusedPlaceholderRange,
+
+    /// The search plan of the before pattern has several guesses that are not constrained by
+    /// already matched regions, for example disjoint parts.
+    ExpensiveSearch,
+}
+
+impl LintKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::UnreachableRule => "unreachable rule",
+            Self::NoopRule => "no-op rule",
+            Self::ShadowedRule => "shadowed rule",
+            Self::UnusedPlaceholderRange => "unused choice list",
+            Self::ExpensiveSearch => "expensive pattern",
+        }
+    }
+}
+
+/// Warning about a program that compiles but probably doesn't do what was intended.
+#[derive(Debug, Clone)]
+pub struct Lint {
+    pub kind: LintKind,
+    pub message: String,
+    pub bounds: Vec<Rect<i64>>,
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.kind.as_str(), self.message)?;
+        if let Some(bounds) = self.bounds.first() {
+            write!(f, " at ({}, {})", bounds.left(), bounds.top())?;
+        }
+        Ok(())
+    }
+}
+
+impl Lint {
+    fn for_rule(kind: LintKind, message: impl Into<String>, rule: &GenericRule) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            bounds: rule.source.iter().map(|source| source.bounds).collect(),
+        }
+    }
+}
+
+fn is_local(rule: &GenericRule) -> bool {
+    rule.source
+        .as_ref()
+        .is_some_and(|source| source.library.is_none())
+}
+
+fn is_noop(rule: &GenericRule) -> bool {
+    rule.instances.iter().all(|instance| {
+        instance.rule.fills.is_empty()
+            && instance
+                .rule
+                .draws
+                .iter()
+                .all(|draw| draw.pixel_materials.is_empty())
+    })
+}
+
+/// Colors of all materials that can occur in the world while the program runs. Only rule
+/// instances that can match add their after colors, until no more colors are added.
+fn available_colors(program: &Program, world: &World) -> HashSet<Rgb8> {
+    let mut colors = HashSet::default();
+
+    for (region_key, region) in &world.topology().regions {
+        if !program.source.contains(region_key) {
+            colors.insert(region.material.rgb);
+        }
+    }
+
+    let mut pending: Vec<&RuleInstance> = program
+        .iter_rule_instances()
+        .map(|(_, instance)| instance)
+        .collect();
+    loop {
+        let (reachable, unreachable): (Vec<_>, Vec<_>) = pending
+            .into_iter()
+            .partition(|instance| !is_unreachable(instance, &colors));
+        if reachable.is_empty() {
+            return colors;
+        }
+        for instance in reachable {
+            colors.extend(instance.after.iter().map(|(_, material)| material.rgb));
+        }
+        pending = unreachable;
+    }
+}
+
+/// Number of unconstrained guesses above which a pattern is considered expensive. Each of them
+/// multiplies the search time by the number of regions of its material.
+const MAX_UNCONSTRAINED_GUESSES: usize = 1;
+
+fn unconstrained_guesses(rule: &GenericRule) -> usize {
+    rule.instances
+        .iter()
+        .map(|instance| {
+            instance
+                .rule
+                .before
+                .search_strategy
+                .main_plan
+                .unconstrained_guesses()
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+fn is_unreachable(instance: &RuleInstance, available_colors: &HashSet<Rgb8>) -> bool {
+    instance
+        .rule
+        .before
+        .material_map
+        .iter()
+        .filter(|(_, material)| material.is_normal() || material.is_solid())
+        .any(|(_, material)| !available_colors.contains(&material.rgb))
+}
+
+/// Find rules that can never match or never change anything and unused choice lists. Only the
+/// rules and ranges of the world itself are linted, not imported ones.
+pub fn lint_program(program: &Program, world: &World) -> Vec<Lint> {
+    let mut lints = Vec::new();
+
+    let available_colors = available_colors(program, world);
+
+    for (i_rule, rule) in program.rules.iter().enumerate() {
+        if !is_local(rule) {
+            continue;
+        }
+
+        if is_noop(rule) {
+            lints.push(Lint::for_rule(
+                LintKind::NoopRule,
+                "Rule does not change anything",
+                rule,
+            ));
+            continue;
+        }
+
+        if rule
+            .instances
+            .iter()
+            .all(|instance| is_unreachable(instance, &available_colors))
+        {
+            lints.push(Lint::for_rule(
+                LintKind::UnreachableRule,
+                "Rule uses a material that never exists in the world",
+                rule,
+            ));
+            continue;
+        }
+
+        // Each instance has an identical before pattern in an earlier rule that changes something.
+        let earlier_rules = &program.rules[..i_rule];
+        let shadowed = rule.instances.iter().all(|instance| {
+            let before = instance.before.clone().translated_to_zero();
+            earlier_rules
+                .iter()
+                .filter(|earlier_rule| !is_noop(earlier_rule))
+                .flat_map(|earlier_rule| &earlier_rule.instances)
+                .any(|earlier_instance| {
+                    earlier_instance.before.clone().translated_to_zero() == before
+                })
+        });
+        if shadowed {
+            lints.push(Lint::for_rule(
+                LintKind::ShadowedRule,
+                "An earlier rule has the same before pattern",
+                rule,
+            ));
+        }
+
+        let unconstrained = unconstrained_guesses(rule);
+        if unconstrained > MAX_UNCONSTRAINED_GUESSES {
+            lints.push(Lint::for_rule(
+                LintKind::ExpensiveSearch,
+                format!(
+                    "Pattern needs {unconstrained} guesses that are not constrained by other \
+                     regions, the search can be very slow"
+                ),
+                rule,
+            ));
+        }
+    }
+
+    // Placeholders that are part of local rules
+    let material_map = world.material_map();
+    let topology = world.topology();
+    let placeholders: Vec<_> = program
+        .rules
+        .iter()
+        .filter(|rule| is_local(rule))
+        .filter_map(|rule| rule.source.as_ref())
+        .flat_map(|source| &source.keys)
+        .filter_map(|region_key| topology.regions.get(region_key))
+        .filter(|region| region.material == Material::RULE_PLACEHOLDER)
+        .map(|region| {
+            material_map
+                .left_of_border(region.boundary.outer_border())
+                .translated_to_zero()
+        })
+        .collect();
+
+    for placeholder_range in &program.placeholder_ranges {
+        if placeholder_range.library.is_some() {
+            continue;
+        }
+
+        if !placeholders.contains(&placeholder_range.placeholder) {
+            let bounds = Rect::iter_bounds(
+                placeholder_range
+                    .source
+                    .iter()
+                    .filter_map(|region_key| topology.regions.get(region_key))
+                    .map(|region| region.bounds()),
+            );
+            lints.push(Lint {
+                kind: LintKind::UnusedPlaceholderRange,
+                message: "Choice list is not used by any rule".to_string(),
+                bounds: vec![bounds],
+            });
+        }
+    }
+
+    lints
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        compiler::Compiler,
+        lints::{LintKind, lint_program},
+        material::Material,
+        math::pixel::Pixel,
+        world::World,
+    };
+
+    fn lint_kinds(path: &str) -> Vec<LintKind> {
+        let world = World::load(path).unwrap();
+        let program = Compiler::new().compile(&world).unwrap();
+        lint_program(&program, &world)
+            .into_iter()
+            .map(|lint| lint.kind)
+            .collect()
+    }
+
+    #[test]
+    fn no_lints() {
+        assert_eq!(lint_kinds("test_resources/compiler/basic_1/world.png"), []);
+        assert_eq!(lint_kinds("test_resources/compiler/b/world.png"), []);
+    }
+
+    /// The yellow square is removed so the yellow to green rule can never match.
+    #[test]
+    fn unreachable() {
+        let mut world = World::load("test_resources/compiler/basic_1/world.png").unwrap();
+        let square = [(2, 10), (3, 10), (2, 11), (3, 11)];
+        world.draw(
+            square
+                .into_iter()
+                .map(|(x, y)| (Pixel::new(x, y), Material::TRANSPARENT)),
+        );
+
+        let program = Compiler::new().compile(&world).unwrap();
+        let lints = lint_program(&program, &world);
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].kind, LintKind::UnreachableRule);
+    }
+
+    #[test]
+    fn shadowed() {
+        assert_eq!(
+            lint_kinds("test_resources/lints/shadowed.png"),
+            [LintKind::ShadowedRule]
+        );
+    }
+
+    /// The before pattern consists of three disjoint regions
+    #[test]
+    fn expensive_search() {
+        assert_eq!(
+            lint_kinds("test_resources/lints/expensive.png"),
+            [LintKind::ExpensiveSearch]
+        );
+    }
+
+    /// The only rule is erased so neither choice list is used.
+    #[test]
+    fn unused_placeholder_range() {
+        let mut world = World::load("test_resources/compiler/generic/a/world.png").unwrap();
+        let program = Compiler::new().compile(&world).unwrap();
+        let rule_bounds = program.rules[0].source.as_ref().unwrap().bounds;
+        world.draw(
+            rule_bounds
+                .iter_half_open()
+                .map(|point| (Pixel::new(point.x, point.y), Material::TRANSPARENT)),
+        );
+
+        assert_eq!(
+            lint_program(&Compiler::new().compile(&world).unwrap(), &world)
+                .into_iter()
+                .map(|lint| lint.kind)
+                .collect::<Vec<_>>(),
+            [LintKind::UnusedPlaceholderRange; 2]
+        );
+    }
+
+    #[test]
+    fn noop() {
+        assert_eq!(
+            lint_kinds("test_resources/compiler/fail_noop/world.png"),
+            [LintKind::NoopRule]
+        );
+    }
+}
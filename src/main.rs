@@ -3,55 +3,6 @@
 
 use log::warn;
 
-// pub fn color_replace() {
-//     for entry in WalkDir::new("./").into_iter().filter_map(Result::ok) {
-//         let extension = entry
-//             .path()
-//             .extension()
-//             .and_then(|ext| ext.to_str())
-//             .map(|ext| ext.to_lowercase());
-//
-//         if extension.as_deref() != Some("png") {
-//             continue;
-//         }
-//
-//         // Load png
-//         let Ok(bitmap) = RgbaField::load(entry.path()) else {
-//             println!("Failed to load {:?}", entry.path());
-//             continue;
-//         };
-//
-//         let legacy_rule_frame_color = Rgba8::from_hex("360C29B4").unwrap();
-//         // alpha = 180 = 0xB4
-//         let rule_frame_color = Rgba8::from_hex("360C29B4").unwrap();
-//         let legacy_rule_arrow_color = Rgba8::from_hex("FF6E00B5").unwrap();
-//         // alpha = 181 = 0xB5
-//         let rule_arrow_color = Rgba8::from_hex("FF6E00B4").unwrap();
-//
-//         // Replace colors
-//         let result = bitmap.map(|&color| {
-//             if color == legacy_rule_frame_color {
-//                 rule_frame_color
-//             } else if color == legacy_rule_arrow_color {
-//                 rule_arrow_color
-//             } else {
-//                 color
-//             }
-//         });
-//
-//         if result != bitmap {
-//             println!("Saving {:?}", entry.path());
-//             if let Err(_) = result.save(entry.path()) {
-//                 println!("Failed to save {:?}", entry.path());
-//             }
-//         } else {
-//             println!("{:?} not modified", entry.path());
-//         }
-//
-//         println!("Processed file {:?}", entry.path());
-//     }
-// }
-
 #[cfg(not(target_arch = "wasm32"))]
 pub fn main_editor() {
     unsafe {
@@ -90,6 +41,16 @@ pub fn main() {
 
         tracy_client::Client::start();
 
+        // Run a command line command instead of the editor if one is given
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        if !args.is_empty() {
+            if let Err(err) = topolang::cli::run(&args) {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+            return;
+        }
+
         // topolang::benchmarks::benchmark_run();
 
         // topolang::benchmarks::benchmark_compile();
@@ -101,7 +62,5 @@ pub fn main() {
         // topolang::benchmarks::benchmark_cellular_automaton();
 
         main_editor();
-
-        // color_replace();
     }
 }
@@ -116,6 +116,10 @@ impl Material {
     pub const RULE_CHOICE_RGB: Rgb8 = Rgb(0x0f, 0x5f, 0x94);
     pub const RULE_CHOICE: Self = Self::new(Self::RULE_CHOICE_RGB, MaterialClass::Rule);
 
+    /// Frame around an encoded path of a rule library to import, see `Compiler::compile_imports`
+    pub const RULE_IMPORT_RGB: Rgb8 = Rgb(0x6b, 0x3f, 0xa0);
+    pub const RULE_IMPORT: Self = Self::new(Self::RULE_IMPORT_RGB, MaterialClass::Rule);
+
     pub const LINK_RGB: Rgb8 = Rgb8::new(0x00, 0x00, 0xEE);
     pub const LINK: Self = Self::new(Self::LINK_RGB, MaterialClass::Special);
 
@@ -250,17 +254,16 @@ impl Material {
             MaterialClass::Temporary => panic!("Cannot convert Temporary Material to Rgba8"),
         }
     }
-}
 
-impl From<Rgba8> for Material {
     /// Some materials have multiple variants that are considered equivalent. For example
     /// "rule before" and "rule after" can have different alpha values that are used to improve
-    /// how the regions look by giving them a border effect.
-    fn from(rgba: Rgba8) -> Self {
+    /// how the regions look by giving them a border effect. None if the alpha value doesn't
+    /// encode a material class or the color is not a rule color.
+    pub fn try_from_rgba(rgba: Rgba8) -> Option<Self> {
         let rgb = rgba.rgb();
         let Rgba { r, g, b, a } = rgba;
 
-        if a == Self::OPAQUE_ALPHA {
+        let material = if a == Self::OPAQUE_ALPHA {
             Self::new(rgb, MaterialClass::Normal)
         } else if a == 0 {
             Self::TRANSPARENT
@@ -270,7 +273,8 @@ impl From<Rgba8> for Material {
                 Self::RULE_AFTER_RGB => Self::RULE_AFTER,
                 Self::RULE_PLACEHOLDER_RGB => Self::RULE_PLACEHOLDER,
                 Self::RULE_CHOICE_RGB => Self::RULE_CHOICE,
-                _ => unimplemented!(),
+                Self::RULE_IMPORT_RGB => Self::RULE_IMPORT,
+                _ => return None,
             }
         } else if Self::SOLID_DARKEN_ALPHA_RANGE.contains(&a) {
             let alpha_offset = a - Self::SOLID_DARKEN_ALPHA_RANGE.start;
@@ -292,8 +296,20 @@ impl From<Rgba8> for Material {
         } else if Self::SPECIAL_ALPHAS.contains(&a) {
             Self::new(rgb, MaterialClass::Special)
         } else {
-            unimplemented!("Alpha {a} not supported");
-        }
+            return None;
+        };
+        Some(material)
+    }
+}
+
+impl From<Rgba8> for Material {
+    /// Panics for alphas and rule colors that don't encode a material, use
+    /// `Material::try_from_rgba` or `image_import` for images from elsewhere.
+    fn from(rgba: Rgba8) -> Self {
+        let Some(material) = Self::try_from_rgba(rgba) else {
+            unimplemented!("Rgba {} not supported", rgba.hex());
+        };
+        material
     }
 }
 
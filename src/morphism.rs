@@ -82,6 +82,11 @@ impl Morphism {
         }
     }
 
+    /// Number of elements of the domain that are mapped
+    pub fn assigned_count(&self) -> usize {
+        self.region_map.len() + self.seam_map.len() + self.corner_map.len() + self.border_map.len()
+    }
+
     pub fn insert(&mut self, element: Element, phi_element: Element) {
         match (element, phi_element) {
             (Element::Region(region_key), Element::Region(phi_region_key)) => {
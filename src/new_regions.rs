@@ -8,7 +8,7 @@ use crate::{
 };
 use ahash::{HashMap, HashSet};
 use itertools::Itertools;
-use std::collections::{BTreeMap, btree_map::Entry};
+use std::collections::{BTreeMap, BTreeSet};
 
 pub type CycleMinSide = Side;
 
@@ -313,45 +313,80 @@ impl ConnectedCycleGroups {
     pub fn from_cycles(cycles: &BoundaryCycles) -> Self {
         let _tracy_span = tracy_client::span!("ConnectedCycleGroups::from_cycles");
 
-        // Maps minimal side of a cycle to the minimal side of the outer cycle
-        let mut cycle_to_outer_cycle: BTreeMap<CycleMinSide, CycleMinSide> = BTreeMap::new();
+        let mut cycle_groups = Self {
+            cycle_to_outer_cycle: BTreeMap::new(),
+            outer_cycle_to_group: BTreeMap::new(),
+        };
+        cycle_groups.group_cycles(cycles, cycles.cycles.keys().copied());
+        cycle_groups
+    }
+
+    /// Update the groups after `BoundaryCycles::update`. The groups with the outer cycles in
+    /// `discarded` are dissolved and their remaining cycles are grouped again together with the
+    /// `rebuilt_cycles`. All other groups are kept, so `discarded` has to contain every group with
+    /// a cycle that touches an updated pixel. Returns the outer cycles of the new groups.
+    #[inline(never)]
+    pub fn update(
+        &mut self,
+        cycles: &BoundaryCycles,
+        discarded: impl IntoIterator<Item = CycleMinSide>,
+        rebuilt_cycles: &[CycleMinSide],
+    ) -> Vec<CycleMinSide> {
+        let _tracy_span = tracy_client::span!("ConnectedCycleGroups::update");
+
+        let mut affected_cycles: BTreeSet<CycleMinSide> = rebuilt_cycles.iter().copied().collect();
+        for outer_cycle_min_side in discarded {
+            let Some(cycle_group) = self.outer_cycle_to_group.remove(&outer_cycle_min_side) else {
+                continue;
+            };
+
+            for cycle_min_side in cycle_group.cycle_min_sides {
+                self.cycle_to_outer_cycle.remove(&cycle_min_side);
+                // Cycles touching the updated pixels were removed or rebuilt
+                if cycles.cycles.contains_key(&cycle_min_side) {
+                    affected_cycles.insert(cycle_min_side);
+                }
+            }
+        }
+
+        self.group_cycles(cycles, affected_cycles.into_iter())
+    }
 
-        for &min_side in cycles.cycles.keys() {
+    /// Assign each cycle to a group. The cycles have to be sorted and a group is either kept as a
+    /// whole or all of its cycles are passed. Returns the outer cycles of the new groups.
+    fn group_cycles(
+        &mut self,
+        cycles: &BoundaryCycles,
+        sorted_cycles: impl Iterator<Item = CycleMinSide>,
+    ) -> Vec<CycleMinSide> {
+        let mut new_groups = Vec::new();
+
+        for min_side in sorted_cycles {
             // Find out if cycle is ccw or cw, meaning it's an outer or inner border.
             assert!(min_side.name == SideName::Left || min_side.name == SideName::BottomRight);
 
             if is_outer_border(min_side) {
-                cycle_to_outer_cycle.insert(min_side, min_side);
+                self.cycle_to_outer_cycle.insert(min_side, min_side);
+
+                let bounds = cycles.cycles[&min_side].bounds;
+                self.outer_cycle_to_group
+                    .insert(min_side, CycleGroup::new(min_side, bounds));
+                new_groups.push(min_side);
             } else {
                 // min_side.left_pixel is contained in the region bounded by the corresponding
-                // cycle.
+                // cycle. The hit cycle is smaller than `min_side`, so it was grouped already.
                 let hit_cycle =
                     cycles.walk_to_boundary_cycle_from_inside_region(min_side.left_pixel);
-                let &outer_cycle = cycle_to_outer_cycle.get(&hit_cycle).unwrap();
-                cycle_to_outer_cycle.insert(min_side, outer_cycle);
+                let outer_cycle = self.cycle_to_outer_cycle[&hit_cycle];
+                self.cycle_to_outer_cycle.insert(min_side, outer_cycle);
+                self.outer_cycle_to_group
+                    .get_mut(&outer_cycle)
+                    .unwrap()
+                    .add_inner_cycle(min_side);
             }
         }
 
-        // TODO: Possible move this to separate function, might not always be required.
-
-        // Maps the minimal side of an outer cycle to the minimal sides of all connected cycles, first
-        // item in the list is the minimal side of the outer cycle itself.
-        let mut outer_cycle_to_group: BTreeMap<CycleMinSide, CycleGroup> = BTreeMap::new();
-        for (&cycle_min_side, &outer_cycle_min_side) in cycle_to_outer_cycle.iter() {
-            match outer_cycle_to_group.entry(outer_cycle_min_side) {
-                Entry::Vacant(vacant) => {
-                    assert_eq!(cycle_min_side, outer_cycle_min_side);
-                    let bounds = cycles.cycles[&cycle_min_side].bounds;
-                    vacant.insert(CycleGroup::new(cycle_min_side, bounds));
-                }
-                Entry::Occupied(mut occupied) => occupied.get_mut().add_inner_cycle(cycle_min_side),
-            }
-        }
-
-        Self {
-            cycle_to_outer_cycle,
-            outer_cycle_to_group,
-        }
+        new_groups
     }
 
     /// Sorted by min_side
@@ -1,5 +1,6 @@
 use crate::{
     field::{Field, FieldIndex, MaterialField, RgbaField},
+    image_import::{UnknownAlpha, import_image},
     material::Material,
     math::{
         pixel::Side,
@@ -265,9 +266,11 @@ impl Pixmap<Material> {
             .map(|material| material.unwrap_or(default).to_rgba())
     }
 
+    /// Fails for pixels that don't encode a material, see `import_image` to map them instead.
     pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
         let rgba_field = RgbaField::load(path)?;
-        Ok(MaterialMap::from(rgba_field))
+        let (material_map, _) = import_image(&rgba_field, UnknownAlpha::Reject, None)?;
+        Ok(material_map)
     }
 
     pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
@@ -0,0 +1,201 @@
+//! Run settings and descriptive metadata stored in the text chunks of a png, so a png that is
+//! shared or loaded from disk starts like a built-in demo.
+//!
+//! The settings are `tEXt` chunks with `topolang:` keys, title and description use the standard
+//! `Title` and `Description` keywords as `iTXt` chunks so they can contain any unicode.
+
+use crate::{
+    math::{point::Point, rect::Rect},
+    run_mode::{RunMode, RunSettings, RunSpeed},
+    utils::ReflectEnum,
+};
+use anyhow::{Context, anyhow};
+use std::io::Cursor;
+
+const RUN_MODE_KEY: &str = "topolang:run_mode";
+const RUN_SPEED_KEY: &str = "topolang:run_speed";
+/// `<left> <top> <width> <height>` of the world rect the camera initially shows
+const CAMERA_KEY: &str = "topolang:camera";
+const TITLE_KEY: &str = "Title";
+const DESCRIPTION_KEY: &str = "Description";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PngMetadata {
+    pub run_settings: Option<RunSettings>,
+
+    /// World rect the camera should fit when the png is loaded
+    pub camera_rect: Option<Rect<i64>>,
+
+    pub title: Option<String>,
+    pub description: Option<String>,
+}
+
+impl PngMetadata {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Read the metadata from the chunks before the image data. Images that aren't pngs have no
+    /// metadata.
+    pub fn read(memory: &[u8]) -> anyhow::Result<Self> {
+        let mut decoder = png::Decoder::new(Cursor::new(memory));
+        decoder.set_ignore_text_chunk(false);
+        let Ok(reader) = decoder.read_info() else {
+            return Ok(Self::default());
+        };
+        Self::from_info(reader.info())
+    }
+
+    /// Parse the metadata from the text chunks of an already decoded png header, the text chunks
+    /// are only kept if the decoder was created with `set_ignore_text_chunk(false)`.
+    pub fn from_info(info: &png::Info) -> anyhow::Result<Self> {
+        let latin1_texts = info
+            .uncompressed_latin1_text
+            .iter()
+            .map(|chunk| (chunk.keyword.as_str(), chunk.text.clone()));
+        let utf8_texts = info
+            .utf8_text
+            .iter()
+            .filter_map(|chunk| Some((chunk.keyword.as_str(), chunk.get_text().ok()?)));
+
+        let mut metadata = Self::default();
+        for (key, text) in latin1_texts.chain(utf8_texts) {
+            match key {
+                RUN_MODE_KEY => {
+                    let mode = RunMode::from_str(&text)
+                        .ok_or_else(|| anyhow!("Invalid run mode {text}"))?;
+                    metadata.run_settings_mut().mode = mode;
+                }
+                RUN_SPEED_KEY => {
+                    let speed = RunSpeed::from_str(&text)
+                        .ok_or_else(|| anyhow!("Invalid run speed {text}"))?;
+                    metadata.run_settings_mut().speed = speed;
+                }
+                CAMERA_KEY => {
+                    let numbers: Vec<i64> = text
+                        .split_whitespace()
+                        .map(str::parse)
+                        .collect::<Result<_, _>>()
+                        .with_context(|| format!("Invalid camera rect {text:?}"))?;
+                    let [left, top, width, height] = numbers[..] else {
+                        anyhow::bail!("Expected 4 numbers in camera rect {text:?}");
+                    };
+                    metadata.camera_rect =
+                        Some(Rect::low_size(Point(left, top), Point(width, height)));
+                }
+                TITLE_KEY => metadata.title = Some(text),
+                DESCRIPTION_KEY => metadata.description = Some(text),
+                _ => {}
+            }
+        }
+
+        Ok(metadata)
+    }
+
+    /// Add the metadata as text chunks, they are written with the header.
+    pub fn write<W: std::io::Write>(&self, encoder: &mut png::Encoder<W>) -> anyhow::Result<()> {
+        if let Some(run_settings) = self.run_settings {
+            encoder.add_text_chunk(RUN_MODE_KEY.into(), run_settings.mode.as_str().into())?;
+            encoder.add_text_chunk(RUN_SPEED_KEY.into(), run_settings.speed.as_str().into())?;
+        }
+        if let Some(rect) = self.camera_rect {
+            let text = format!(
+                "{} {} {} {}",
+                rect.left(),
+                rect.top(),
+                rect.width(),
+                rect.height()
+            );
+            encoder.add_text_chunk(CAMERA_KEY.into(), text)?;
+        }
+        if let Some(title) = &self.title {
+            encoder.add_itxt_chunk(TITLE_KEY.into(), title.clone())?;
+        }
+        if let Some(description) = &self.description {
+            encoder.add_itxt_chunk(DESCRIPTION_KEY.into(), description.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Missing mode or speed default to the settings of a png without metadata.
+    fn run_settings_mut(&mut self) -> &mut RunSettings {
+        self.run_settings
+            .get_or_insert(RunSettings::new(RunMode::Paused, RunSpeed::Hz10))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        field::RgbaField,
+        math::{point::Point, rect::Rect},
+        png_metadata::PngMetadata,
+        run_mode::{RunMode, RunSettings, RunSpeed},
+    };
+
+    #[test]
+    fn round_trip() {
+        let rgba_field = RgbaField::load("test_resources/compiler/basic_1/world.png").unwrap();
+        let metadata = PngMetadata {
+            run_settings: Some(RunSettings::new(RunMode::Run, RunSpeed::Hz30)),
+            camera_rect: Some(Rect::low_size(Point(-4, 2), Point(32, 24))),
+            title: Some("Zähler".to_string()),
+            description: Some("Counts up\nforever".to_string()),
+        };
+
+        let png = rgba_field.to_png_with_metadata(&metadata).unwrap();
+        let (loaded, loaded_metadata) = RgbaField::load_with_metadata(&png).unwrap();
+        assert_eq!(loaded, rgba_field);
+        assert_eq!(loaded_metadata, metadata);
+
+        // Pngs without metadata, for example the ones saved before, still load
+        let png = rgba_field.to_png().unwrap();
+        let (_, loaded_metadata) = RgbaField::load_with_metadata(&png).unwrap();
+        assert!(loaded_metadata.is_empty());
+    }
+
+    /// load_with_metadata() decodes pngs itself, it should agree with the image crate
+    #[test]
+    fn load_color_types() {
+        let encode = |color: png::ColorType, depth: png::BitDepth, data: &[u8]| {
+            let mut png = Vec::new();
+            let mut encoder = png::Encoder::new(&mut png, 2, 1);
+            encoder.set_color(color);
+            encoder.set_depth(depth);
+            if color == png::ColorType::Indexed {
+                encoder.set_palette(vec![255, 0, 0, 0, 0, 255]);
+                encoder.set_trns(vec![128, 255]);
+            }
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(data).unwrap();
+            writer.finish().unwrap();
+            png
+        };
+
+        let pngs = [
+            encode(png::ColorType::Grayscale, png::BitDepth::Eight, &[0, 200]),
+            encode(
+                png::ColorType::Grayscale,
+                png::BitDepth::One,
+                &[0b0100_0000],
+            ),
+            encode(
+                png::ColorType::GrayscaleAlpha,
+                png::BitDepth::Eight,
+                &[7, 0, 9, 255],
+            ),
+            encode(
+                png::ColorType::Rgb,
+                png::BitDepth::Sixteen,
+                &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12],
+            ),
+            encode(png::ColorType::Indexed, png::BitDepth::Eight, &[1, 0]),
+            std::fs::read("test_resources/compiler/basic_1/world.png").unwrap(),
+        ];
+        for png in pngs {
+            let (loaded, metadata) = RgbaField::load_with_metadata(&png).unwrap();
+            assert_eq!(loaded, RgbaField::load_from_memory(&png).unwrap());
+            assert!(metadata.is_empty());
+        }
+    }
+}
@@ -0,0 +1,644 @@
+//! Native project file format. Unlike PNGs, which encode the material class in the alpha
+//! channel, it stores the materials of the world losslessly together with the editor state.
+//!
+//! The format is line based text:
+//! ```text
+//! topolang project
+//! version 2
+//! run_mode Paused
+//! run_speed 10
+//! camera <offset x> <offset y> <scale>
+//! grid_size 8
+//! palette PICO-8
+//! materials <count>
+//! <rrggbb> <class>
+//! pixels <left> <top> <width> <height>
+//! <row>
+//! history <snapshots> <head>
+//! snapshot <cause>
+//! pixels <left> <top> <width> <height>
+//! <row>
+//! snapshot <cause>
+//! diff <left> <top> <width> <height>
+//! <row>
+//! selection <left> <top> <width> <height>
+//! <row>
+//! ```
+//! Header fields are `key value` lines, unknown keys are ignored. Each row is a list of indices
+//! into the materials, `.` for no material and `n*x` for `x` repeated `n` times.
+//!
+//! The optional undo history follows the pixels of the world, from the root snapshot to the most
+//! recent one. A snapshot with the same bounds as the one before only stores the rect of pixels
+//! that changed (`diff`, omitted if none did), otherwise all its `pixels`. The selection is only
+//! stored if it changed, `selection none` if it was removed.
+
+use crate::{
+    camera::Camera,
+    field::Field,
+    history::{SavedHistory, SavedSnapshot, SnapshotCause},
+    material::{Material, MaterialClass},
+    math::{
+        point::Point,
+        rect::Rect,
+        rgba8::{Rgb8, Rgba8},
+    },
+    pixmap::MaterialMap,
+    run_mode::{RunMode, RunSettings, RunSpeed},
+    utils::ReflectEnum,
+};
+use ahash::HashMap;
+use anyhow::{Context, anyhow, bail};
+use itertools::Itertools;
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    iter::{self, Peekable},
+    path::Path,
+};
+
+/// First line of every project file
+const MAGIC: &str = "topolang project";
+
+/// Largest number of pixels of a rect in a project file, larger sizes are rejected before any
+/// memory is allocated for them.
+const MAX_PIXELS: i64 = 1 << 24;
+
+/// Largest total number of pixels of the snapshots of a history. Each snapshot holds a copy of the
+/// world, even if the file only stores the pixels that changed.
+const MAX_HISTORY_PIXELS: i64 = 1 << 28;
+
+/// Version written by `Project::encode`
+pub const VERSION: u32 = 2;
+
+pub const EXTENSION: &str = "topo";
+
+#[derive(Debug, Clone)]
+pub struct Project {
+    pub material_map: MaterialMap,
+    pub run_settings: RunSettings,
+    pub camera: Camera,
+    pub grid_size: Option<i64>,
+
+    /// Name of the palette of the color chooser
+    pub palette: Option<String>,
+
+    /// Undo history of the editor
+    pub history: Option<SavedHistory>,
+}
+
+/// Header fields by key, the value is the rest of the line
+type Header = BTreeMap<String, String>;
+
+impl Project {
+    pub fn new(material_map: MaterialMap) -> Self {
+        Self {
+            material_map,
+            run_settings: RunSettings::new(RunMode::Paused, RunSpeed::Hz10),
+            camera: Camera::default(),
+            grid_size: None,
+            palette: None,
+            history: None,
+        }
+    }
+
+    /// True if `content` starts like a project file, PNGs and other files don't.
+    pub fn is_project(content: &[u8]) -> bool {
+        content.starts_with(MAGIC.as_bytes())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Self::decode(&content)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        std::fs::write(path, self.encode())?;
+        Ok(())
+    }
+
+    pub fn encode(&self) -> String {
+        let mut text = String::new();
+        writeln!(text, "{MAGIC}").unwrap();
+        writeln!(text, "version {VERSION}").unwrap();
+        writeln!(text, "run_mode {}", self.run_settings.mode.as_str()).unwrap();
+        writeln!(text, "run_speed {}", self.run_settings.speed.as_str()).unwrap();
+        let Camera { offset, scale } = self.camera;
+        writeln!(text, "camera {} {} {scale}", offset.x, offset.y).unwrap();
+        if let Some(grid_size) = self.grid_size {
+            writeln!(text, "grid_size {grid_size}").unwrap();
+        }
+        if let Some(palette) = &self.palette {
+            writeln!(text, "palette {palette}").unwrap();
+        }
+
+        let snapshots = self.history.iter().flat_map(|history| &history.snapshots);
+        let history_maps = snapshots
+            .flat_map(|snapshot| iter::once(&snapshot.material_map).chain(&snapshot.selection));
+        let materials: Vec<Material> = iter::once(&self.material_map)
+            .chain(history_maps)
+            .flat_map(|material_map| material_map.values())
+            .unique()
+            .sorted()
+            .collect();
+        let material_indices: HashMap<Material, usize> = materials
+            .iter()
+            .enumerate()
+            .map(|(i, &material)| (material, i))
+            .collect();
+
+        writeln!(text, "materials {}", materials.len()).unwrap();
+        for material in &materials {
+            let Rgb8 { r, g, b } = material.rgb;
+            writeln!(text, "{r:02x}{g:02x}{b:02x} {}", material.class.as_str()).unwrap();
+        }
+
+        write_pixels(&mut text, "pixels", &self.material_map, &material_indices);
+
+        if let Some(history) = &self.history {
+            write_history(&mut text, history, &material_indices);
+        }
+
+        text
+    }
+
+    pub fn decode(text: &str) -> anyhow::Result<Self> {
+        let mut lines = text.lines().map(str::trim_end).peekable();
+        if lines.next() != Some(MAGIC) {
+            bail!("Not a project file");
+        }
+
+        // Header up to the materials
+        let mut header = Header::new();
+        let n_materials = loop {
+            let line = lines.next().context("Missing materials")?;
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            if key == "materials" {
+                break value.parse::<usize>().context("Invalid material count")?;
+            }
+            header.insert(key.to_string(), value.to_string());
+        };
+
+        let version: u32 = header
+            .get("version")
+            .context("Missing version")?
+            .parse()
+            .context("Invalid version")?;
+        migrate(version, &mut header)?;
+
+        let materials = (0..n_materials)
+            .map(|_| parse_material(lines.next().context("Missing material")?))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let line = lines.next().context("Missing pixels")?;
+        let Some(("pixels", bounds)) = line.split_once(' ') else {
+            bail!("Expected pixels, found {line:?}");
+        };
+        let material_map = read_pixels(&mut lines, parse_rect(bounds)?, &materials)?;
+
+        let mut project = Self::new(material_map);
+        if let Some(line) = lines.next().filter(|line| !line.is_empty()) {
+            let Some(("history", counts)) = line.split_once(' ') else {
+                bail!("Expected history, found {line:?}");
+            };
+            project.history = Some(read_history(&mut lines, counts, &materials)?);
+        }
+        if let Some(mode) = header.get("run_mode") {
+            project.run_settings.mode =
+                RunMode::from_str(mode).ok_or_else(|| anyhow!("Invalid run mode {mode}"))?;
+        }
+        if let Some(speed) = header.get("run_speed") {
+            project.run_settings.speed =
+                RunSpeed::from_str(speed).ok_or_else(|| anyhow!("Invalid run speed {speed}"))?;
+        }
+        if let Some(camera) = header.get("camera") {
+            let [x, y, scale] = parse_numbers::<f64, 3>(camera)?;
+            project.camera = Camera {
+                offset: Point(x, y),
+                scale,
+            };
+        }
+        if let Some(grid_size) = header.get("grid_size") {
+            project.grid_size = Some(grid_size.parse().context("Invalid grid size")?);
+        }
+        project.palette = header.get("palette").cloned();
+
+        Ok(project)
+    }
+}
+
+/// Bring the header of a file written with `version` up to date. Each format change adds a step
+/// here that converts the header of the previous version, so old files keep loading.
+fn migrate(version: u32, _header: &mut Header) -> anyhow::Result<()> {
+    match version {
+        VERSION => Ok(()),
+        // Version 2 added the history after the pixels, the header is unchanged
+        1 => Ok(()),
+        version if version > VERSION => {
+            bail!("Project was saved by a newer version (format {version}), update to open it")
+        }
+        version => bail!("Unknown project format {version}"),
+    }
+}
+
+/// `<keyword> <rect>` followed by the rows of `material_map` within the rect
+fn write_rows(
+    text: &mut String,
+    keyword: &str,
+    material_map: &MaterialMap,
+    rect: Rect<i64>,
+    material_indices: &HashMap<Material, usize>,
+) {
+    writeln!(
+        text,
+        "{keyword} {} {} {} {}",
+        rect.left(),
+        rect.top(),
+        rect.width(),
+        rect.height()
+    )
+    .unwrap();
+
+    for y in rect.top()..rect.bottom() {
+        let row = (rect.left()..rect.right()).map(|x| {
+            material_map
+                .get(Point(x, y))
+                .map(|material| material_indices[&material])
+        });
+        let tokens = row.dedup_with_count().map(|(count, cell)| {
+            let cell = cell.map_or(".".to_string(), |i| i.to_string());
+            if count == 1 {
+                cell
+            } else {
+                format!("{count}*{cell}")
+            }
+        });
+        writeln!(text, "{}", tokens.format(" ")).unwrap();
+    }
+}
+
+fn write_pixels(
+    text: &mut String,
+    keyword: &str,
+    material_map: &MaterialMap,
+    material_indices: &HashMap<Material, usize>,
+) {
+    let bounds = material_map.bounding_rect();
+    write_rows(text, keyword, material_map, bounds, material_indices);
+}
+
+fn write_history(
+    text: &mut String,
+    history: &SavedHistory,
+    material_indices: &HashMap<Material, usize>,
+) {
+    writeln!(text, "history {} {}", history.snapshots.len(), history.head).unwrap();
+
+    let mut previous: Option<&SavedSnapshot> = None;
+    for snapshot in &history.snapshots {
+        writeln!(text, "snapshot {}", snapshot.cause.as_str()).unwrap();
+
+        let material_map = &snapshot.material_map;
+        match previous.filter(|previous| {
+            previous.material_map.bounding_rect() == material_map.bounding_rect()
+        }) {
+            Some(previous) => {
+                let changed =
+                    Rect::index_bounds(material_map.bounding_rect().iter_indices().filter(
+                        |&pixel| previous.material_map.get(pixel) != material_map.get(pixel),
+                    ));
+                if changed.has_positive_area() {
+                    write_rows(text, "diff", material_map, changed, material_indices);
+                }
+            }
+            None => write_pixels(text, "pixels", material_map, material_indices),
+        }
+
+        let previous_selection = previous.and_then(|previous| previous.selection.as_ref());
+        if snapshot.selection.as_ref() != previous_selection {
+            match &snapshot.selection {
+                Some(selection) => write_pixels(text, "selection", selection, material_indices),
+                None => writeln!(text, "selection none").unwrap(),
+            }
+        }
+
+        previous = Some(snapshot);
+    }
+}
+
+/// Rows of the cells within `bounds`, see module documentation
+fn read_cells<'a>(
+    lines: &mut impl Iterator<Item = &'a str>,
+    bounds: Rect<i64>,
+    materials: &[Material],
+) -> anyhow::Result<Vec<Option<Material>>> {
+    // Grows with the rows that were read, the bounds are not trusted
+    let mut cells = Vec::new();
+    for _ in 0..bounds.height() {
+        let row = lines.next().context("Missing pixel row")?;
+        let row_start = cells.len();
+        for token in row.split_whitespace() {
+            let (count, cell) = match token.split_once('*') {
+                Some((count, cell)) => (count.parse().context("Invalid count")?, cell),
+                None => (1, token),
+            };
+            let material = match cell {
+                "." => None,
+                index => {
+                    let index: usize = index.parse().context("Invalid material index")?;
+                    Some(
+                        *materials
+                            .get(index)
+                            .context("Material index out of range")?,
+                    )
+                }
+            };
+            if count > bounds.width() as usize - (cells.len() - row_start) {
+                bail!("Pixel row is too long");
+            }
+            cells.extend(iter::repeat_n(material, count));
+        }
+        if cells.len() - row_start != bounds.width() as usize {
+            bail!("Pixel row has wrong length");
+        }
+    }
+    Ok(cells)
+}
+
+fn read_pixels<'a>(
+    lines: &mut impl Iterator<Item = &'a str>,
+    bounds: Rect<i64>,
+    materials: &[Material],
+) -> anyhow::Result<MaterialMap> {
+    let cells = read_cells(lines, bounds, materials)?;
+    Ok(MaterialMap::new(Field::from_linear(bounds, cells)))
+}
+
+fn read_history<'a, I: Iterator<Item = &'a str>>(
+    lines: &mut Peekable<I>,
+    counts: &str,
+    materials: &[Material],
+) -> anyhow::Result<SavedHistory> {
+    let [n_snapshots, head] = parse_numbers::<usize, 2>(counts)?;
+    if head >= n_snapshots {
+        bail!("History head out of range");
+    }
+
+    // Not preallocated, the count is not trusted
+    let mut snapshots: Vec<SavedSnapshot> = Vec::new();
+    let mut history_pixels = 0;
+    for _ in 0..n_snapshots {
+        let line = lines.next().context("Missing snapshot")?;
+        let Some(("snapshot", cause)) = line.split_once(' ') else {
+            bail!("Expected snapshot, found {line:?}");
+        };
+        let cause = SnapshotCause::from_str(cause)
+            .with_context(|| format!("Invalid snapshot cause {cause:?}"))?;
+
+        let previous = snapshots.last();
+        let mut material_map = previous.map(|previous| previous.material_map.clone());
+        let mut selection = previous.and_then(|previous| previous.selection.clone());
+        while let Some((keyword, value)) = lines.peek().and_then(|line| line.split_once(' ')) {
+            match (keyword, value) {
+                ("pixels", bounds) => {
+                    lines.next();
+                    material_map = Some(read_pixels(lines, parse_rect(bounds)?, materials)?);
+                }
+                ("diff", rect) => {
+                    lines.next();
+                    let rect = parse_rect(rect)?;
+                    let material_map = material_map
+                        .as_mut()
+                        .context("Diff without previous snapshot")?;
+                    if !material_map.bounding_rect().contains_rect(rect) {
+                        bail!("Diff outside of the snapshot");
+                    }
+                    let cells = read_cells(lines, rect, materials)?;
+                    for (pixel, material) in rect.iter_indices().zip(cells) {
+                        material_map.put(pixel, material);
+                    }
+                }
+                ("selection", "none") => {
+                    lines.next();
+                    selection = None;
+                }
+                ("selection", bounds) => {
+                    lines.next();
+                    selection = Some(read_pixels(lines, parse_rect(bounds)?, materials)?);
+                }
+                _ => break,
+            }
+        }
+
+        let material_map = material_map.context("Missing snapshot pixels")?;
+        history_pixels += material_map.bounding_rect().area();
+        history_pixels += selection
+            .as_ref()
+            .map_or(0, |selection| selection.bounding_rect().area());
+        if history_pixels > MAX_HISTORY_PIXELS {
+            bail!("History is too large");
+        }
+
+        snapshots.push(SavedSnapshot {
+            material_map,
+            selection,
+            cause,
+        });
+    }
+
+    Ok(SavedHistory { snapshots, head })
+}
+
+fn parse_rect(text: &str) -> anyhow::Result<Rect<i64>> {
+    let [left, top, width, height] = parse_numbers::<i64, 4>(text)?;
+    if width < 0 || height < 0 {
+        bail!("Negative pixels size");
+    }
+    if width
+        .checked_mul(height)
+        .is_none_or(|pixels| pixels > MAX_PIXELS)
+    {
+        bail!("Pixels size too large");
+    }
+    if left.checked_add(width).is_none() || top.checked_add(height).is_none() {
+        bail!("Pixels out of range");
+    }
+    Ok(Rect::low_size(Point(left, top), Point(width, height)))
+}
+
+fn parse_material(line: &str) -> anyhow::Result<Material> {
+    let (hex, class) = line
+        .split_once(' ')
+        .with_context(|| format!("Invalid material {line:?}"))?;
+    let rgba =
+        Rgba8::from_hex(&format!("{hex}ff")).with_context(|| format!("Invalid color {hex:?}"))?;
+    let class =
+        MaterialClass::from_str(class).with_context(|| format!("Invalid class {class:?}"))?;
+    Ok(Material::new(rgba.rgb(), class))
+}
+
+fn parse_numbers<T: std::str::FromStr, const N: usize>(text: &str) -> anyhow::Result<[T; N]> {
+    let numbers: Vec<T> = text
+        .split_whitespace()
+        .map(|number| {
+            number
+                .parse()
+                .map_err(|_| anyhow!("Invalid number {number:?}"))
+        })
+        .try_collect()?;
+    numbers
+        .try_into()
+        .map_err(|_| anyhow!("Expected {N} numbers in {text:?}"))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        history::{History, SnapshotCause},
+        material::Material,
+        math::{pixel::Pixel, point::Point, rect::Rect, rgba8::Rgb8},
+        pixmap::MaterialMap,
+        project::{Project, VERSION},
+        run_mode::{RunMode, RunSpeed},
+        view::Selection,
+        world::World,
+    };
+
+    #[test]
+    fn round_trip() {
+        let world = World::load("test_resources/compiler/basic_1/world.png").unwrap();
+        let mut material_map = world.material_map().clone();
+        // Has no alpha encoding, could not be saved as png
+        material_map.set(Pixel::new(0, 0), Material::temporary(Rgb8::RED));
+
+        let mut project = Project::new(material_map.clone());
+        project.run_settings.mode = RunMode::Slowmo;
+        project.run_settings.speed = RunSpeed::Hz30;
+        project.camera.offset = Point(-3.25, 7.0);
+        project.camera.scale = 0.125;
+        project.grid_size = Some(8);
+        project.palette = Some("PICO-8".to_string());
+
+        let text = project.encode();
+        assert!(Project::is_project(text.as_bytes()));
+        let decoded = Project::decode(&text).unwrap();
+        assert_eq!(decoded.material_map, material_map);
+        assert_eq!(decoded.run_settings, project.run_settings);
+        assert_eq!(decoded.camera.offset, project.camera.offset);
+        assert_eq!(decoded.camera.scale, project.camera.scale);
+        assert_eq!(decoded.grid_size, Some(8));
+        assert_eq!(decoded.palette.as_deref(), Some("PICO-8"));
+    }
+
+    #[test]
+    fn history() {
+        let world = World::load("test_resources/compiler/basic_1/world.png").unwrap();
+        let material_map = world.material_map().clone();
+        let mut history = History::new(material_map.clone(), None);
+
+        let mut brushed = material_map.clone();
+        brushed.set(Pixel::new(3, 4), Material::temporary(Rgb8::RED));
+        brushed.set(Pixel::new(5, 6), Material::BLACK);
+        history.add_snapshot(brushed.clone(), None, SnapshotCause::Brush);
+
+        let selected =
+            MaterialMap::filled(Rect::low_size(Point(2, 2), Point(3, 2)), Material::BLACK);
+        history.add_snapshot(
+            brushed.clone(),
+            Some(Selection::new(selected)),
+            SnapshotCause::Selected,
+        );
+
+        let resized =
+            MaterialMap::filled(Rect::low_size(Point(0, 0), Point(4, 4)), Material::BLACK);
+        history.add_snapshot(resized, None, SnapshotCause::Resized);
+        history.undo();
+
+        let mut project = Project::new(material_map);
+        project.history = Some(history.to_saved());
+        let text = project.encode();
+        // Only the changed pixels are stored
+        assert!(text.contains("diff 3 4 3 3\n"));
+        assert!(text.contains("selection 2 2 3 2\n"));
+        assert!(text.contains("selection none\n"));
+
+        let decoded = Project::decode(&text).unwrap();
+        assert_eq!(decoded.history, project.history);
+
+        let restored = History::from_saved(decoded.history.unwrap());
+        assert_eq!(restored.head.cause(), SnapshotCause::Selected);
+        assert_eq!(restored.head.material_map(), &brushed);
+        assert_eq!(restored.to_saved(), history.to_saved());
+    }
+
+    /// Sizes and counts are checked before memory is allocated for them.
+    #[test]
+    fn untrusted_sizes() {
+        let material_map =
+            MaterialMap::filled(Rect::low_size(Point(0, 0), Point(2, 1)), Material::BLACK);
+        let text = Project::new(material_map).encode();
+        assert!(text.contains("pixels 0 0 2 1\n2*0\n"));
+
+        for (from, to) in [
+            ("pixels 0 0 2 1", "pixels 0 0 4000000000 4000000000"),
+            ("pixels 0 0 2 1", "pixels 9223372036854775807 0 2 1"),
+            ("pixels 0 0 2 1", "pixels 0 0 8192 8192"),
+            // The largest allowed rect without any rows
+            ("pixels 0 0 2 1\n2*0\n", "pixels 0 0 4096 4096\n"),
+            ("\n2*0\n", "\n1000000000000*0\n"),
+            ("\n2*0\n", "\n1*0 2*0\n"),
+        ] {
+            assert!(Project::decode(&text.replace(from, to)).is_err());
+        }
+
+        let history = format!("{text}history 1000000000000 0\n");
+        assert!(Project::decode(&history).is_err());
+
+        // Snapshots without changes still copy the world
+        let mut history = format!("{text}history 20 0\nsnapshot Brush\npixels 0 0 4096 4096\n");
+        history += &"4096*0\n".repeat(4096);
+        history += &"snapshot Brush\n".repeat(19);
+        let err = Project::decode(&history).unwrap_err();
+        assert_eq!(err.to_string(), "History is too large");
+    }
+
+    #[test]
+    fn load_world() {
+        let world = World::load("test_resources/compiler/basic_1/world.png").unwrap();
+        let path = std::env::temp_dir().join("topolang_load_world.topo");
+        Project::new(world.material_map().clone())
+            .save(&path)
+            .unwrap();
+
+        let loaded = World::load(&path).unwrap();
+        assert_eq!(loaded.material_map(), world.material_map());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    /// Optional fields can be missing and unknown fields are ignored.
+    #[test]
+    fn minimal() {
+        let text = "topolang project\nversion 1\nunknown field\nmaterials 1\n000000 Normal\n\
+                    pixels 2 3 3 2\n. 0 .\n3*0\n";
+        let project = Project::decode(text).unwrap();
+        assert_eq!(project.run_settings.mode, RunMode::Paused);
+        assert_eq!(project.grid_size, None);
+
+        let material_map = &project.material_map;
+        assert_eq!(material_map.bounding_rect().low(), Point(2, 3));
+        assert_eq!(material_map.get(Pixel::new(2, 3)), None);
+        assert_eq!(material_map.get(Pixel::new(3, 3)), Some(Material::BLACK));
+        assert_eq!(material_map.values().count(), 4);
+    }
+
+    #[test]
+    fn newer_version() {
+        let material_map =
+            MaterialMap::filled(Rect::low_size(Point(0, 0), Point(1, 1)), Material::BLACK);
+        let text = Project::new(material_map).encode().replace(
+            &format!("version {VERSION}"),
+            &format!("version {}", VERSION + 1),
+        );
+        assert!(Project::decode(&text).is_err());
+        assert!(Project::decode("not a project").is_err());
+    }
+}
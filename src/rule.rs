@@ -5,8 +5,8 @@ use crate::{
     morphism::Morphism,
     new_regions::{BoundaryCycles, ConnectedCycleGroups, CycleMinSide, Sides},
     pixmap::{MaterialMap, Pixmap},
-    solver::plan::SearchStrategy,
-    topology::{MaskedTopology, Region, RegionKey, Topology},
+    solver::plan::{SearchBudget, SearchLimits, SearchStrategy},
+    topology::{Region, RegionKey, Topology},
     world::World,
 };
 use ahash::{HashMap, HashSet};
@@ -47,7 +47,7 @@ pub struct InputCondition {
     pub region_key: RegionKey,
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct CanvasInput {
     pub mouse_position: Point<i64>,
     pub left_mouse_down: bool,
@@ -150,9 +150,20 @@ impl DrawRegion {
 
 #[derive(Debug, Clone, Copy)]
 pub struct RuleApplicationContext<'a> {
-    pub contained: Option<RegionKey>,
     pub excluded: &'a HashSet<RegionKey>,
     pub input: &'a CanvasInput,
+
+    pub search_limits: SearchLimits,
+
+    /// `monotonic_time` all searches have to end by
+    pub tick_deadline: f64,
+}
+
+impl RuleApplicationContext<'_> {
+    /// Budget for a search that starts now
+    pub fn search_budget(&self) -> SearchBudget {
+        self.search_limits.budget(self.tick_deadline)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -329,37 +340,25 @@ impl Rule {
         modified
     }
 
-    /// Apply rule until `world` has changed or is stable under `rule`, only matches that contain
-    /// `contained` are considered.
-    /// Returns true if a modification was made.
-    pub fn apply(&self, world: &mut World, ctx: &RuleApplicationContext) -> bool {
-        let tracy_span = tracy_client::span!("Rule::apply");
-        tracy_span.emit_color(0xFFFFFF);
-        tracy_span.emit_text(&self.before.debug_id_str());
-
+    /// Would `substitute` change the world for the match `phi`.
+    pub fn modifies(&self, phi: &Morphism, world: &World) -> bool {
         let topology = world.topology();
-        let masked_topology = MaskedTopology::new(topology, ctx.excluded);
-        let solutions = self
-            .before
-            .search_strategy
-            .solutions(&masked_topology, ctx.contained);
-
-        for phi in solutions {
-            // Check if input conditions are satisfied
-            if !self
-                .before
-                .input_conditions_satisfied(&phi, world.topology(), ctx.input)
-            {
-                continue;
-            }
-
-            let modified = self.substitute(&phi, world);
-            if modified {
-                return true;
-            }
-        }
-
-        false
+        let fills = self
+            .fills
+            .iter()
+            .any(|fill| topology[phi[fill.region_key]].material != fill.material);
+
+        // If no fill is effective, the material map is the same when the draws are applied
+        fills
+            || self.draws.iter().any(|draw_region| {
+                let offset = phi[draw_region.anchor_region_key].left_pixel;
+                draw_region
+                    .pixel_materials
+                    .iter()
+                    .any(|&(pixel, material)| {
+                        world.material_map().get(pixel + offset) != Some(material)
+                    })
+            })
     }
 }
 
@@ -369,7 +368,7 @@ mod test {
         field::RgbaField,
         pixmap::MaterialMap,
         rule::{Pattern, Rule},
-        solver::plan::{SearchStrategy, SimpleGuessChooser},
+        solver::plan::{SearchBudget, SearchStrategy, SimpleGuessChooser},
         topology::Topology,
         world::World,
     };
@@ -401,7 +400,7 @@ mod test {
         while let Some(phi) = rule
             .before
             .search_strategy
-            .solutions(&world.topology().into(), None)
+            .solutions(&world.topology().into(), None, &SearchBudget::unlimited())
             .first()
         {
             let changed = rule.substitute(&phi, &mut world);
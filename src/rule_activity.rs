@@ -50,6 +50,11 @@ impl RuleActivity {
                 continue;
             };
 
+            // Imported rules are not visible in the world
+            if source.library.is_some() {
+                continue;
+            }
+
             let area = source.area();
             let outline = Self::area_outline(area.iter().copied());
             outlines.insert(source.modified_time, Arc::new(outline));
@@ -93,13 +98,18 @@ impl RuleActivity {
                 continue;
             };
 
+            // Imported rules have no outline
+            let Some(outline) = self.outlines.get(&rule_mtime) else {
+                continue;
+            };
+
             // intensity is 1 if real_time == application.real_time and 0 delta_t later, so
             let animation_time = real_time - application.real_time;
             if animation_time > 0.0 {
                 let alpha = 1.0 - 3.0 * animation_time;
                 if alpha > 0.0 {
                     let glow = Glow {
-                        outline: self.outlines.get(&rule_mtime).unwrap().clone(),
+                        outline: outline.clone(),
                         alpha,
                     };
                     glows.push(glow);
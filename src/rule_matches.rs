@@ -0,0 +1,441 @@
+use crate::{
+    morphism::Morphism,
+    rule::{Rule, RuleApplicationContext},
+    rule_stats::RuleStats,
+    topology::{AtomicTime, MaskedTopology, Topology},
+    utils::monotonic_time,
+    world::World,
+};
+use std::{collections::VecDeque, ops::ControlFlow};
+
+/// Match of a rule pattern in the world and the modification time of the world when it was found.
+/// It stays valid as long as none of the regions it maps to is modified.
+#[derive(Debug, Clone)]
+pub struct CachedMatch {
+    pub phi: Morphism,
+    pub found_time: AtomicTime,
+}
+
+impl CachedMatch {
+    pub fn is_valid(&self, topology: &Topology) -> bool {
+        self.phi.region_map.values().all(|region_key| {
+            topology
+                .regions
+                .get(region_key)
+                .is_some_and(|region| region.modified_time <= self.found_time)
+        })
+    }
+}
+
+/// Matches of a single rule, maintained incrementally across modifications of the world. Only the
+/// regions modified since the last sync are searched for new matches, matches that touch modified
+/// regions are dropped when they are tried. Applying a rule therefore searches around the regions
+/// it changed instead of repeating the search around all modified regions after each application.
+///
+/// Only matches that modify the world are cached. A sync stops searching once it found
+/// `MAX_NEW_MATCHES` of them, the remaining ones are searched for after those are used up.
+///
+/// Unlike a RETE network, partial matches are not cached. A match that becomes invalid is discarded
+/// as a whole and found again by the search around the modified regions if it still exists, and
+/// each of those searches starts from scratch.
+///
+/// Rules with input conditions are not cached because the mouse can move without the world
+/// changing. Each sync searches for a single match that contains the region under the mouse.
+#[derive(Debug, Clone, Default)]
+pub struct RuleMatches {
+    /// Matches that have not been tried yet
+    matches: VecDeque<CachedMatch>,
+
+    /// The last sync stopped early, the search is continued once `matches` are used up.
+    incomplete: bool,
+}
+
+impl RuleMatches {
+    /// If more than this fraction of all regions was modified, a single search of the whole
+    /// world is faster than a search around each modified region.
+    const FULL_SEARCH_FRACTION: usize = 4;
+
+    /// A sync stops searching once it found this many new matches.
+    pub const MAX_NEW_MATCHES: usize = 64;
+
+    pub fn len(&self) -> usize {
+        self.matches.len()
+    }
+
+    /// Search for matches that contain a region modified after `cursor` and move `cursor` to the
+    /// last modification. If the search stops early `cursor` is only moved past the regions that
+    /// were searched completely. Returns false if a search exceeded its budget, the matches and
+    /// `cursor` are unchanged in that case.
+    pub fn sync(
+        &mut self,
+        rule: &Rule,
+        world: &World,
+        ctx: &RuleApplicationContext,
+        cursor: &mut AtomicTime,
+        stats: &mut RuleStats,
+    ) -> bool {
+        if !rule.before.input_conditions.is_empty() {
+            return self.sync_input(rule, world, ctx, stats);
+        }
+
+        let topology = world.topology();
+        if self.incomplete {
+            if self
+                .matches
+                .iter()
+                .any(|cached_match| Self::is_applicable(rule, world, ctx, cached_match))
+            {
+                return true;
+            }
+            self.matches.clear();
+            self.incomplete = false;
+        }
+
+        let Some((last_mtime, _)) = topology.last_modification() else {
+            return true;
+        };
+        if last_mtime <= *cursor {
+            return true;
+        }
+
+        let _tracy_span = tracy_client::span!("RuleMatches::sync");
+
+        let masked_topology = MaskedTopology::new(topology, ctx.excluded);
+        let modified: Vec<_> = topology
+            .modifications_after(*cursor)
+            .filter(|(_, region_key)| !ctx.excluded.contains(region_key))
+            .collect();
+        stats.cursor_advances += modified.len() as u64;
+
+        let full_search =
+            *cursor < 0 || Self::FULL_SEARCH_FRACTION * modified.len() > topology.regions.len();
+        // Each search with the cursor it completes
+        let searches: Vec<_> = if full_search {
+            vec![(None, *cursor)]
+        } else {
+            // A match can be found through each of its modified regions
+            modified
+                .into_iter()
+                .map(|(mtime, region_key)| (Some(region_key), mtime - 1))
+                .collect()
+        };
+
+        let search_start = monotonic_time();
+        // Few enough that checking for duplicates by comparison is cheap
+        let mut solutions: Vec<Morphism> = Vec::new();
+        let mut stopped_at = None;
+        let mut exceeded = false;
+        for (contained, search_cursor) in searches {
+            stats.searches += 1;
+            let budget = ctx.search_budget();
+            let flow = rule.before.search_strategy.try_search(
+                &masked_topology,
+                contained,
+                &budget,
+                |phi| {
+                    stats.solutions += 1;
+                    if rule.modifies(phi, world) && !solutions.contains(phi) {
+                        solutions.push(phi.clone());
+                        if solutions.len() >= Self::MAX_NEW_MATCHES {
+                            return ControlFlow::Break(());
+                        }
+                    }
+                    ControlFlow::Continue(())
+                },
+            );
+
+            if budget.is_exceeded() {
+                exceeded = true;
+                break;
+            }
+            if flow.is_break() {
+                stopped_at = Some(search_cursor);
+                break;
+            }
+        }
+        stats.search_time += monotonic_time() - search_start;
+
+        if exceeded {
+            return false;
+        }
+
+        if full_search {
+            // Cached matches are found again if they are still valid
+            self.matches.clear();
+        }
+        self.matches
+            .extend(solutions.into_iter().map(|phi| CachedMatch {
+                phi,
+                found_time: last_mtime,
+            }));
+        self.incomplete = stopped_at.is_some();
+        *cursor = stopped_at.unwrap_or(last_mtime);
+        true
+    }
+
+    fn is_applicable(
+        rule: &Rule,
+        world: &World,
+        ctx: &RuleApplicationContext,
+        cached_match: &CachedMatch,
+    ) -> bool {
+        let topology = world.topology();
+        cached_match.is_valid(topology)
+            && rule
+                .before
+                .input_conditions_satisfied(&cached_match.phi, topology, ctx.input)
+            && rule.modifies(&cached_match.phi, world)
+    }
+
+    /// Replace the matches by the first match that satisfies the input conditions and modifies
+    /// the world. Such a match contains the region under the mouse or a region containing it.
+    fn sync_input(
+        &mut self,
+        rule: &Rule,
+        world: &World,
+        ctx: &RuleApplicationContext,
+        stats: &mut RuleStats,
+    ) -> bool {
+        self.matches.clear();
+
+        let topology = world.topology();
+        let Some(mouse_region) = topology.region_key_at(ctx.input.mouse_position) else {
+            return true;
+        };
+
+        let _tracy_span = tracy_client::span!("RuleMatches::sync_input");
+
+        let masked_topology = MaskedTopology::new(topology, ctx.excluded);
+        let search_start = monotonic_time();
+        let mut exceeded = false;
+        for region_key in topology.iter_containing_regions(mouse_region) {
+            stats.searches += 1;
+            let budget = ctx.search_budget();
+            let phi = rule.before.search_strategy.find(
+                &masked_topology,
+                Some(region_key),
+                &budget,
+                |phi| {
+                    stats.solutions += 1;
+                    rule.before
+                        .input_conditions_satisfied(phi, topology, ctx.input)
+                        && rule.modifies(phi, world)
+                },
+            );
+
+            if let Some(phi) = phi {
+                self.matches.push_back(CachedMatch {
+                    phi,
+                    found_time: topology.last_modification().map_or(-1, |(mtime, _)| mtime),
+                });
+                break;
+            }
+
+            if budget.is_exceeded() {
+                exceeded = true;
+                break;
+            }
+        }
+        stats.search_time += monotonic_time() - search_start;
+
+        !exceeded
+    }
+
+    /// Apply the first valid cached match that modifies the world, the matches tried before it are
+    /// removed. Only the tried matches are checked for validity. Returns true if the world was
+    /// modified.
+    pub fn apply(&mut self, rule: &Rule, world: &mut World, ctx: &RuleApplicationContext) -> bool {
+        let Some(i) = self
+            .matches
+            .iter()
+            .position(|cached_match| Self::is_applicable(rule, world, ctx, cached_match))
+        else {
+            self.matches.clear();
+            return false;
+        };
+
+        self.matches.drain(..i);
+        let cached_match = self.matches.pop_front().unwrap();
+        let modified = rule.substitute(&cached_match.phi, world);
+        debug_assert!(modified);
+        modified
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        compiler::Compiler,
+        material::Material,
+        math::{pixel::Pixel, point::Point, rect::Rect, rgba8::Rgb},
+        pixmap::MaterialMap,
+        rule::{CanvasInput, RuleApplicationContext},
+        rule_matches::RuleMatches,
+        rule_stats::RuleStats,
+        solver::plan::{SearchBudget, SearchLimits},
+        topology::MaskedTopology,
+        world::World,
+    };
+    use itertools::Itertools;
+
+    #[test]
+    fn invalidate_and_resync() {
+        let mut world = World::load("test_resources/compiler/basic_1/world.png").unwrap();
+        let program = Compiler::new().compile(&world).unwrap();
+        let rule = &program.rules[0].instances[0].rule;
+
+        let input = CanvasInput::default();
+        let ctx = RuleApplicationContext {
+            excluded: &program.source,
+            input: &input,
+            search_limits: SearchLimits::UNLIMITED,
+            tick_deadline: f64::INFINITY,
+        };
+
+        let mut matches = RuleMatches::default();
+        let mut cursor = -1;
+        let mut stats = RuleStats::default();
+        assert!(matches.sync(rule, &world, &ctx, &mut cursor, &mut stats));
+        assert_eq!(matches.len(), 1);
+
+        let square = [(2, 10), (3, 10), (2, 11), (3, 11)].map(|(x, y)| Pixel::new(x, y));
+
+        // Painting the yellow square invalidates the match
+        let blue = Material::normal(Rgb(0x29, 0xAD, 0xFF));
+        world.draw(square.into_iter().map(|pixel| (pixel, blue)));
+        assert!(!matches.apply(rule, &mut world, &ctx));
+        assert_eq!(matches.len(), 0);
+
+        // Painting it yellow again creates a new match
+        let yellow = Material::normal(Rgb(0xFF, 0xEC, 0x27));
+        world.draw(square.into_iter().map(|pixel| (pixel, yellow)));
+        assert!(matches.sync(rule, &world, &ctx, &mut cursor, &mut stats));
+        assert_eq!(matches.len(), 1);
+        assert!(matches.apply(rule, &mut world, &ctx));
+        assert_eq!(matches.len(), 0);
+    }
+
+    /// A sync stops once it found enough matches, the others are found after those were applied.
+    #[test]
+    fn stops_early() {
+        // The world of basic_1 with 100 more yellow squares below it
+        let basic = MaterialMap::load("test_resources/compiler/basic_1/world.png").unwrap();
+        let mut material_map = MaterialMap::filled(
+            Rect::low_size(Point(0, 0), Point(32, 52)),
+            Material::TRANSPARENT,
+        );
+        material_map.blit(&basic);
+        let yellow = Material::normal(Rgb(0xFF, 0xEC, 0x27));
+        for (i, j) in (0..10).cartesian_product(0..10) {
+            let square = Rect::low_size(Point(3 * i, 20 + 3 * j), Point(2, 2));
+            material_map.fill_rect(square, yellow);
+        }
+        let mut world = World::from_material_map(material_map);
+
+        let program = Compiler::new().compile(&world).unwrap();
+        let rule = &program.rules[0].instances[0].rule;
+
+        let input = CanvasInput::default();
+        let ctx = RuleApplicationContext {
+            excluded: &program.source,
+            input: &input,
+            search_limits: SearchLimits::UNLIMITED,
+            tick_deadline: f64::INFINITY,
+        };
+
+        let mut matches = RuleMatches::default();
+        let mut cursor = -1;
+        let mut stats = RuleStats::default();
+        assert!(matches.sync(rule, &world, &ctx, &mut cursor, &mut stats));
+        assert_eq!(matches.len(), RuleMatches::MAX_NEW_MATCHES);
+        assert_eq!(stats.solutions, RuleMatches::MAX_NEW_MATCHES as u64);
+        assert_eq!(cursor, -1);
+
+        // The drawn squares and the one of the original world
+        let mut applications = 0;
+        while matches.sync(rule, &world, &ctx, &mut cursor, &mut stats)
+            && matches.apply(rule, &mut world, &ctx)
+        {
+            applications += 1;
+        }
+        assert_eq!(applications, 101);
+        assert!(cursor >= 0);
+    }
+
+    /// A search that exceeds its budget leaves the matches and the cursor unchanged.
+    #[test]
+    fn budget_exceeded() {
+        let world = World::load("test_resources/compiler/basic_1/world.png").unwrap();
+        let program = Compiler::new().compile(&world).unwrap();
+        let rule = &program.rules[0].instances[0].rule;
+
+        let input = CanvasInput::default();
+        let mut ctx = RuleApplicationContext {
+            excluded: &program.source,
+            input: &input,
+            search_limits: SearchLimits {
+                max_guesses: 0,
+                ..SearchLimits::UNLIMITED
+            },
+            tick_deadline: f64::INFINITY,
+        };
+
+        let mut matches = RuleMatches::default();
+        let mut cursor = -1;
+        let mut stats = RuleStats::default();
+        assert!(!matches.sync(rule, &world, &ctx, &mut cursor, &mut stats));
+        assert_eq!(cursor, -1);
+        assert_eq!(matches.len(), 0);
+
+        ctx.search_limits = SearchLimits::UNLIMITED;
+        assert!(matches.sync(rule, &world, &ctx, &mut cursor, &mut stats));
+        assert_eq!(matches.len(), 1);
+    }
+
+    /// Rules with input conditions only find a match while the mouse is over it.
+    #[test]
+    fn input_conditions() {
+        let mut world = World::load("test_resources/benchmark/generic_2048.png").unwrap();
+        let program = Compiler::new().compile(&world).unwrap();
+        let rule = program
+            .iter_rule_instances()
+            .map(|(_, instance)| &instance.rule)
+            .find(|rule| !rule.before.input_conditions.is_empty())
+            .unwrap();
+
+        let codom = MaskedTopology::new(world.topology(), &program.source);
+        let phi = rule
+            .before
+            .search_strategy
+            .solutions(&codom, None, &SearchBudget::unlimited())
+            .into_iter()
+            .find(|phi| rule.modifies(phi, &world))
+            .unwrap();
+        let mouse_region = phi[rule.before.input_conditions[0].region_key];
+
+        let mut input = CanvasInput {
+            mouse_position: Pixel::new(-1000, -1000),
+            left_mouse_down: true,
+            left_mouse_click: true,
+            right_mouse_down: true,
+            right_mouse_click: true,
+        };
+        let mut matches = RuleMatches::default();
+        let mut cursor = -1;
+        let mut stats = RuleStats::default();
+        for (mouse_position, expected_len) in
+            [(input.mouse_position, 0), (mouse_region.left_pixel, 1)]
+        {
+            input.mouse_position = mouse_position;
+            let ctx = RuleApplicationContext {
+                excluded: &program.source,
+                input: &input,
+                search_limits: SearchLimits::UNLIMITED,
+                tick_deadline: f64::INFINITY,
+            };
+            assert!(matches.sync(rule, &world, &ctx, &mut cursor, &mut stats));
+            assert_eq!(matches.len(), expected_len);
+            assert_eq!(matches.apply(rule, &mut world, &ctx), expected_len == 1);
+        }
+    }
+}
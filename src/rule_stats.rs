@@ -0,0 +1,149 @@
+use crate::compiler::{GenericRule, Program};
+use std::{
+    fmt::Write,
+    ops::{Add, AddAssign},
+};
+
+/// Profiling counters of a single rule, collected by the `Interpreter`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RuleStats {
+    /// Number of `SearchStrategy::solutions` calls
+    pub searches: u64,
+
+    /// Total number of solutions returned by all searches
+    pub solutions: u64,
+
+    /// Number of times the rule modified the world
+    pub applications: u64,
+
+    /// Time spent searching in seconds
+    pub search_time: f64,
+
+    /// Number of times the cursor was moved past a modification without applying the rule
+    pub cursor_advances: u64,
+}
+
+impl AddAssign for RuleStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.searches += rhs.searches;
+        self.solutions += rhs.solutions;
+        self.applications += rhs.applications;
+        self.search_time += rhs.search_time;
+        self.cursor_advances += rhs.cursor_advances;
+    }
+}
+
+impl Add for RuleStats {
+    type Output = Self;
+
+    fn add(mut self, rhs: Self) -> Self {
+        self += rhs;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleStatsColumn {
+    Searches,
+    Solutions,
+    Applications,
+    SearchTime,
+    CursorAdvances,
+}
+
+impl RuleStatsColumn {
+    pub const ALL: [Self; 5] = [
+        Self::Searches,
+        Self::Solutions,
+        Self::Applications,
+        Self::SearchTime,
+        Self::CursorAdvances,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Searches => "Searches",
+            Self::Solutions => "Solutions",
+            Self::Applications => "Applications",
+            Self::SearchTime => "Search ms",
+            Self::CursorAdvances => "Cursor advances",
+        }
+    }
+
+    pub fn value(self, stats: &RuleStats) -> f64 {
+        match self {
+            Self::Searches => stats.searches as f64,
+            Self::Solutions => stats.solutions as f64,
+            Self::Applications => stats.applications as f64,
+            Self::SearchTime => 1000.0 * stats.search_time,
+            Self::CursorAdvances => stats.cursor_advances as f64,
+        }
+    }
+
+    pub fn format(self, stats: &RuleStats) -> String {
+        match self {
+            Self::SearchTime => format!("{:.2}", self.value(stats)),
+            _ => format!("{}", self.value(stats)),
+        }
+    }
+}
+
+/// Name of a rule for tables, the position of its rule frame
+pub fn rule_label(rule: &GenericRule) -> String {
+    match &rule.source {
+        Some(source) => {
+            let position = format!("({}, {})", source.bounds.left(), source.bounds.top());
+            match &source.library {
+                Some(library) => format!("{} {position}", library.display()),
+                None => position,
+            }
+        }
+        None => "?".to_string(),
+    }
+}
+
+/// Indices of `stats` sorted by `column`, largest first.
+pub fn sorted_by(stats: &[RuleStats], column: RuleStatsColumn) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..stats.len()).collect();
+    indices.sort_by(|&lhs, &rhs| {
+        column
+            .value(&stats[rhs])
+            .total_cmp(&column.value(&stats[lhs]))
+    });
+    indices
+}
+
+/// Quoted so that labels can contain commas, quotes are escaped by doubling them
+fn csv_field(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "\"\""))
+}
+
+/// Table with one row per rule in csv format, `stats` has an entry for each rule of `program`.
+pub fn stats_csv(program: &Program, stats: &[RuleStats]) -> String {
+    let mut csv = String::from("rule");
+    for column in RuleStatsColumn::ALL {
+        write!(csv, ",{}", column.as_str()).unwrap();
+    }
+    csv.push('\n');
+
+    for i in sorted_by(stats, RuleStatsColumn::SearchTime) {
+        csv.push_str(&csv_field(&rule_label(&program.rules[i])));
+        for column in RuleStatsColumn::ALL {
+            write!(csv, ",{}", column.format(&stats[i])).unwrap();
+        }
+        csv.push('\n');
+    }
+
+    csv
+}
+
+#[cfg(test)]
+mod test {
+    use crate::rule_stats::csv_field;
+
+    #[test]
+    fn csv_quotes() {
+        assert_eq!(csv_field("lib/a.png (1, 2)"), r#""lib/a.png (1, 2)""#);
+        assert_eq!(csv_field(r#"say "hi".png"#), r#""say ""hi"".png""#);
+    }
+}
@@ -0,0 +1,74 @@
+//! Worlds embedded in the fragment of a link to the web build, `<page>#world=<png>`. The png is
+//! encoded as url safe base64 and contains the run settings and title as `PngMetadata`, so
+//! small programs can be shared without hosting files. The fragment is never sent to a server.
+
+use crate::{field::RgbaField, png_metadata::PngMetadata};
+use anyhow::Context;
+use data_encoding::BASE64URL_NOPAD;
+
+const WORLD_KEY: &str = "world";
+
+/// Fragment without the leading `#`
+pub fn encode_fragment(rgba_field: &RgbaField, metadata: &PngMetadata) -> anyhow::Result<String> {
+    let png = rgba_field.to_png_with_metadata(metadata)?;
+    Ok(format!("{WORLD_KEY}={}", BASE64URL_NOPAD.encode(&png)))
+}
+
+/// The png in the fragment, `None` if the fragment contains no world. Other `key=value` pairs
+/// separated by `&` are ignored.
+pub fn decode_fragment(fragment: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    let fragment = fragment.strip_prefix('#').unwrap_or(fragment);
+    let Some(encoded) = fragment
+        .split('&')
+        .find_map(|pair| pair.strip_prefix(WORLD_KEY)?.strip_prefix('='))
+    else {
+        return Ok(None);
+    };
+
+    let png = BASE64URL_NOPAD
+        .decode(encoded.as_bytes())
+        .context("Invalid world in link")?;
+    Ok(Some(png))
+}
+
+/// `page_url` with its fragment replaced by `fragment`
+pub fn share_link(page_url: &str, fragment: &str) -> String {
+    let page_url = page_url.split('#').next().unwrap_or_default();
+    format!("{page_url}#{fragment}")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        field::RgbaField,
+        png_metadata::PngMetadata,
+        run_mode::{RunMode, RunSettings, RunSpeed},
+        share_link::{decode_fragment, encode_fragment, share_link},
+    };
+
+    #[test]
+    fn round_trip() {
+        let world = RgbaField::load("test_resources/compiler/basic_1/world.png").unwrap();
+        let metadata = PngMetadata {
+            run_settings: Some(RunSettings::new(RunMode::Run, RunSpeed::Hz30)),
+            title: Some("Basic".to_string()),
+            ..PngMetadata::default()
+        };
+        let fragment = encode_fragment(&world, &metadata).unwrap();
+        let link = share_link("https://example.com/topolang/?demo=turing#old", &fragment);
+        assert_eq!(
+            link,
+            format!("https://example.com/topolang/?demo=turing#{fragment}")
+        );
+
+        let hash = &link[link.find('#').unwrap()..];
+        let png = decode_fragment(&format!("{hash}&other=1"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(RgbaField::load_from_memory(&png).unwrap(), world);
+        assert_eq!(PngMetadata::read(&png).unwrap(), metadata);
+
+        assert_eq!(decode_fragment("#other=1").unwrap(), None);
+        assert!(decode_fragment("#world=not+base64").is_err());
+    }
+}
@@ -12,6 +12,8 @@ pub trait Variables {
 
 pub trait Constraint: Debug + Variables {
     fn is_satisfied(&self, phi: &Morphism, codom: &Topology) -> bool;
+
+    fn name(&self) -> &'static str;
 }
 
 /// Assure
@@ -69,6 +71,10 @@ impl Constraint for PreservesSeam {
             && phi.region_map[&self.left_of_seam_key] == codom.left_of(phi_seam)
             && phi.border_map[&self.on_border_key] == codom.seam_border(phi_seam)
     }
+
+    fn name(&self) -> &'static str {
+        "PreservesSeam"
+    }
 }
 
 /// Only applicable if reverse of seam is in domain of phi
@@ -101,6 +107,10 @@ impl Constraint for PreservesSeamReverse {
 
         phi_seam.is_atom() && phi_seam.atom_reversed() == phi_reverse_seam
     }
+
+    fn name(&self) -> &'static str {
+        "PreservesSeamReverse"
+    }
 }
 
 /// region material must match phi(region) material
@@ -136,6 +146,10 @@ impl Constraint for PreservesMaterial {
         let phi_region = &codom[phi_region_key];
         self.material.matches(phi_region.material)
     }
+
+    fn name(&self) -> &'static str {
+        "PreservesMaterial"
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -170,6 +184,10 @@ impl Constraint for PreservesBorderCount {
         let phi_region = &codom[phi_region_key];
         phi_region.boundary.borders.len() == self.border_count
     }
+
+    fn name(&self) -> &'static str {
+        "PreservesBorderCount"
+    }
 }
 
 /// Unlike the other Constraints this one is expensive to construct because we need to clone Region.
@@ -220,6 +238,10 @@ impl Constraint for PreservesSolid {
 
         phi.is_rigid_on_region(&self.region, &phi_region)
     }
+
+    fn name(&self) -> &'static str {
+        "PreservesSolid"
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -254,6 +276,10 @@ impl Constraint for PreservesBorderOrientation {
         let phi_border = &codom[phi_border_key];
         phi_border.is_outer == self.is_outer
     }
+
+    fn name(&self) -> &'static str {
+        "PreservesBorderOrientation"
+    }
 }
 
 /// Assure mapped seams are not overlapping
@@ -288,6 +314,10 @@ impl Constraint for NonOverlappingSeams {
         let overlapping = codom.are_seams_overlapping(phi_seam_a, phi_seam_b);
         !overlapping
     }
+
+    fn name(&self) -> &'static str {
+        "NonOverlappingSeams"
+    }
 }
 
 /// Assure mapped regions are distinct
@@ -321,6 +351,10 @@ impl Constraint for DistinctRegions {
         let phi_region_b_key = phi.region_map[&self.region_b_key];
         phi_region_a_key != phi_region_b_key
     }
+
+    fn name(&self) -> &'static str {
+        "DistinctRegions"
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -360,6 +394,10 @@ impl Constraint for AnyConstraint {
     fn is_satisfied(&self, phi: &Morphism, codom: &Topology) -> bool {
         self.as_constraint().is_satisfied(phi, codom)
     }
+
+    fn name(&self) -> &'static str {
+        self.as_constraint().name()
+    }
 }
 
 /// Creates constraints that enforce a proper Morphism from dom to codom
@@ -1,4 +1,5 @@
 pub(crate) mod constraints;
 pub(crate) mod element;
+pub(crate) mod near_miss;
 pub(crate) mod plan;
 pub(crate) mod propagations;
@@ -0,0 +1,292 @@
+use crate::{
+    math::rect::Rect,
+    morphism::Morphism,
+    solver::{
+        constraints::{AnyConstraint, Constraint, Variables},
+        element::Element,
+        plan::{
+            ConstraintAction, ConstraintSystem, Guess, SearchBudget, SearchPlan, SearchStrategy,
+        },
+        propagations::{AnyPropagation, Propagation},
+    },
+    topology::{MaskedTopology, RegionKey, Topology},
+};
+use std::ops::ControlFlow;
+
+/// Why the deepest partial morphism of a search could not be extended.
+#[derive(Debug, Clone)]
+pub enum MissCause {
+    /// The guess had nothing to try, e.g. no region with the material of the pattern region.
+    NoCandidates(Guess),
+    Propagation(AnyPropagation),
+    Constraint(AnyConstraint),
+}
+
+impl MissCause {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::NoCandidates(_) => "NoCandidates",
+            Self::Propagation(propagation) => propagation.name(),
+            Self::Constraint(constraint) => constraint.name(),
+        }
+    }
+
+    /// Pattern element the failed guess, propagation or constraint is about
+    pub fn element(&self) -> Element {
+        match self {
+            Self::NoCandidates(guess) => guess.variable(),
+            Self::Propagation(propagation) => propagation.derives(),
+            Self::Constraint(constraint) => constraint.variables()[0],
+        }
+    }
+}
+
+/// Result of diagnosing why a pattern does not match at some location. The search records the
+/// deepest partial morphism it reached and what stopped it there.
+#[derive(Debug, Clone)]
+pub struct NearMiss {
+    /// Deepest partial morphism, a solution if `cause` is None
+    pub phi: Morphism,
+
+    /// None if the pattern matches
+    pub cause: Option<MissCause>,
+
+    /// Number of elements of the pattern, `phi` maps all of them if it is a solution.
+    pub elements: usize,
+
+    /// The search was stopped before it tried everything
+    pub budget_exceeded: bool,
+}
+
+impl NearMiss {
+    pub fn is_match(&self) -> bool {
+        self.cause.is_none()
+    }
+
+    pub fn message(&self) -> String {
+        let Some(cause) = &self.cause else {
+            return "Pattern matches here, input conditions or the rule not changing anything \
+                    prevent it"
+                .to_string();
+        };
+
+        let element = match cause.element() {
+            Element::Region(_) => "region",
+            Element::Border(_) => "border",
+            Element::Seam(_) => "seam",
+            Element::Corner(_) => "corner",
+        };
+        let mut message = format!(
+            "Matched {} of {} pattern elements, then {} failed on a pattern {element}",
+            self.phi.assigned_count(),
+            self.elements,
+            cause.name()
+        );
+        if self.budget_exceeded {
+            message.push_str(" (search stopped early)");
+        }
+        message
+    }
+
+    /// Bounds of the pattern element the cause is about, in the coordinates of the pattern.
+    pub fn element_bounds(&self, dom: &Topology) -> Option<Rect<i64>> {
+        let bounds = match self.cause.as_ref()?.element() {
+            Element::Region(region_key) => dom[region_key].bounds(),
+            Element::Border(border_key) => dom[border_key.region_key].bounds(),
+            Element::Seam(seam) => Rect::cell_rect(seam.start.left_pixel),
+            Element::Corner(corner) => Rect::cell_rect(corner.pixel),
+        };
+        Some(bounds)
+    }
+
+    /// Bounds of the regions of `codom` that `phi` maps to
+    pub fn mapped_bounds(&self, codom: &Topology) -> Vec<Rect<i64>> {
+        self.phi
+            .region_map
+            .values()
+            .map(|&phi_region_key| codom[phi_region_key].bounds())
+            .collect()
+    }
+
+    /// Prefer solutions, then the partial morphism that maps more elements.
+    pub fn is_deeper_than(&self, other: &NearMiss) -> bool {
+        match (self.is_match(), other.is_match()) {
+            (true, false) => true,
+            (false, true) => false,
+            _ => self.phi.assigned_count() > other.phi.assigned_count(),
+        }
+    }
+}
+
+/// Search of a single plan that remembers the deepest partial morphism.
+struct Diagnosis<'a> {
+    plan: &'a SearchPlan,
+    codom: &'a MaskedTopology<'a>,
+    budget: &'a SearchBudget,
+    deepest: Option<(Morphism, Option<MissCause>)>,
+}
+
+impl Diagnosis<'_> {
+    fn record(&mut self, phi: &Morphism, cause: Option<MissCause>) {
+        let deeper = match &self.deepest {
+            Some((deepest, _)) => phi.assigned_count() > deepest.assigned_count(),
+            None => true,
+        };
+        if deeper || cause.is_none() {
+            self.deepest = Some((phi.clone(), cause));
+        }
+    }
+
+    /// Apply the actions of step `i_step` to `phi`, which has the guess of the step assigned,
+    /// and continue with the next step.
+    fn check_and_continue(&mut self, i_step: usize, mut phi: Morphism) -> ControlFlow<()> {
+        let plan = self.plan;
+        for action in &plan.steps[i_step].actions {
+            if action.apply(&mut phi, self.codom).is_err() {
+                let cause = match action {
+                    ConstraintAction::Propagation(propagation) => {
+                        MissCause::Propagation(propagation.clone())
+                    }
+                    ConstraintAction::Constraint(constraint) => {
+                        MissCause::Constraint(constraint.clone())
+                    }
+                };
+                self.record(&phi, Some(cause));
+                return ControlFlow::Continue(());
+            }
+        }
+
+        self.step(i_step + 1, phi)
+    }
+
+    fn step(&mut self, i_step: usize, phi: Morphism) -> ControlFlow<()> {
+        let plan = self.plan;
+        let Some(step) = plan.steps.get(i_step) else {
+            self.record(&phi, None);
+            return ControlFlow::Break(());
+        };
+
+        // Each candidate continues with its own copy, otherwise the recorded morphism would
+        // contain leftovers of other candidates.
+        let (codom, budget) = (self.codom, self.budget);
+        let mut tried = false;
+        let mut guessed = phi.clone();
+        step.guess.guess(&mut guessed, codom, budget, |guessed| {
+            tried = true;
+            self.check_and_continue(i_step, guessed.clone())
+        })?;
+
+        if !tried {
+            self.record(&phi, Some(MissCause::NoCandidates(step.guess)));
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Find out why the pattern of `search_strategy` does not match around `phi_region_key`. Each
+/// region of the pattern `dom` is tried as the preimage of `phi_region_key`, also the ones with a
+/// different material, and the deepest partial morphism of all these searches is returned.
+pub fn diagnose(
+    search_strategy: &SearchStrategy,
+    dom: &Topology,
+    codom: &MaskedTopology,
+    phi_region_key: RegionKey,
+    budget: &SearchBudget,
+) -> Option<NearMiss> {
+    let elements = ConstraintSystem::variables(dom).len();
+
+    let mut deepest: Option<NearMiss> = None;
+    for (_, plan) in &search_strategy.plans {
+        let Some(first_step) = plan.steps.first() else {
+            continue;
+        };
+        let Guess::Region(region_key, _) = first_step.guess else {
+            panic!("First guess must be region");
+        };
+
+        let mut diagnosis = Diagnosis {
+            plan,
+            codom,
+            budget,
+            deepest: None,
+        };
+        let mut phi = Morphism::new();
+        phi.region_map.insert(region_key, phi_region_key);
+        let _ = diagnosis.check_and_continue(0, phi);
+
+        let Some((phi, cause)) = diagnosis.deepest else {
+            continue;
+        };
+        let near_miss = NearMiss {
+            phi,
+            cause,
+            elements,
+            budget_exceeded: budget.is_exceeded(),
+        };
+        let is_match = near_miss.is_match();
+        if deepest
+            .as_ref()
+            .is_none_or(|deepest| near_miss.is_deeper_than(deepest))
+        {
+            deepest = Some(near_miss);
+        }
+        if is_match {
+            break;
+        }
+    }
+
+    deepest
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        compiler::Compiler,
+        math::pixel::Pixel,
+        solver::{
+            constraints::Constraint,
+            near_miss::{MissCause, diagnose},
+            plan::SearchBudget,
+        },
+        topology::MaskedTopology,
+        world::World,
+    };
+
+    /// The yellow square of basic_1 matches, after recoloring it the material constraint fails.
+    #[test]
+    fn near_miss() {
+        let mut world = World::load("test_resources/compiler/basic_1/world.png").unwrap();
+        let program = Compiler::new().compile(&world).unwrap();
+        let pattern = &program.rules[0].instances[0].rule.before;
+        let square = Pixel::new(2, 10);
+
+        let diagnose_square = |world: &World| {
+            let codom = MaskedTopology::new(world.topology(), &program.source);
+            let phi_region_key = world.topology().region_key_at(square).unwrap();
+            diagnose(
+                &pattern.search_strategy,
+                &pattern.topology,
+                &codom,
+                phi_region_key,
+                &SearchBudget::unlimited(),
+            )
+            .unwrap()
+        };
+
+        let near_miss = diagnose_square(&world);
+        assert!(near_miss.is_match());
+        assert_eq!(near_miss.phi.assigned_count(), near_miss.elements);
+
+        let green = world.material_map().get(Pixel::new(11, 3)).unwrap();
+        let pixels = [(2, 10), (3, 10), (2, 11), (3, 11)];
+        world.draw(pixels.into_iter().map(|(x, y)| (Pixel::new(x, y), green)));
+
+        let near_miss = diagnose_square(&world);
+        assert!(!near_miss.is_match());
+        assert!(matches!(
+            near_miss.cause,
+            Some(MissCause::Constraint(ref constraint)) if constraint.name() == "PreservesMaterial"
+        ));
+        assert!(near_miss.element_bounds(&pattern.topology).is_some());
+    }
+}
@@ -7,12 +7,19 @@ use crate::{
         propagations::{AnyPropagation, Propagation, morphism_propagations},
     },
     topology::{BorderKey, MaskedTopology, RegionKey, Seam, Topology, TopologyStatistics},
+    utils::monotonic_time,
 };
 use ahash::{HashMap, HashSet};
+use itertools::Itertools;
+use std::{
+    ops::ControlFlow,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
 
 #[derive(Debug, Clone, Copy)]
 pub enum Guess {
-    Region(RegionKey),
+    /// Only regions of the codomain with a matching material are tried
+    Region(RegionKey, Material),
 
     /// Only interior borders, the outer border is determined by the region
     InteriorBorder(BorderKey),
@@ -24,27 +31,33 @@ pub enum Guess {
 impl Guess {
     pub fn variable(&self) -> Element {
         match self {
-            &Guess::Region(region_key) => region_key.into(),
+            &Guess::Region(region_key, _) => region_key.into(),
             &Guess::InteriorBorder(border_key) => border_key.into(),
             &Guess::Seam(_, seam) => seam.into(),
         }
     }
 
-    pub fn guess(
+    /// Call `f` for each possible assignment of the guessed variable until it breaks. Each
+    /// assignment is paid from `budget`, no more are tried once it is exceeded.
+    pub fn guess<B>(
         &self,
         phi: &mut Morphism,
         codom: &MaskedTopology,
-        mut f: impl FnMut(&mut Morphism),
-    ) {
+        budget: &SearchBudget,
+        mut f: impl FnMut(&mut Morphism) -> ControlFlow<B>,
+    ) -> ControlFlow<B> {
         let tracy_span = tracy_client::span!("guess");
         tracy_span.emit_color(0xFF00FF);
 
         match self {
-            &Self::Region(region_key) => {
-                for phi_region_key in codom.visible_region_keys() {
+            &Self::Region(region_key, material) => {
+                for phi_region_key in codom.visible_region_keys_matching(material) {
+                    if !budget.spend() {
+                        return ControlFlow::Continue(());
+                    }
                     phi.region_map.insert(region_key, phi_region_key);
                     // println!("Guess Region {region_key} -> Region {phi_region_key}");
-                    f(phi);
+                    f(phi)?;
                 }
             }
             &Self::InteriorBorder(border_key) => {
@@ -52,20 +65,125 @@ impl Guess {
                 let phi_region = &codom.inner[phi_region_key];
                 for i_border in 1..phi_region.boundary.borders.len() {
                     let phi_border_key = BorderKey::new(phi_region_key, i_border);
+                    if !budget.spend() {
+                        return ControlFlow::Continue(());
+                    }
                     phi.border_map.insert(border_key, phi_border_key);
-                    f(phi);
+                    f(phi)?;
                 }
             }
             &Self::Seam(border_key, seam) => {
                 let phi_border_key = phi.border_map[&border_key];
                 let phi_border = &codom.inner[phi_border_key];
                 for phi_seam in phi_border.atomic_seams() {
+                    if !budget.spend() {
+                        return ControlFlow::Continue(());
+                    }
                     phi.seam_map.insert(seam, phi_seam);
                     // println!("Guess {seam:?} -> {phi_seam:?}");
-                    f(phi);
+                    f(phi)?;
                 }
             }
         }
+
+        ControlFlow::Continue(())
+    }
+}
+
+/// Limits of the searches of the interpreter, see `SearchBudget`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchLimits {
+    /// Maximum number of guesses of a single search
+    pub max_guesses: u64,
+
+    /// Maximum time of a single search in seconds
+    pub max_search_time: f64,
+
+    /// Maximum time of all searches of a single tick in seconds
+    pub max_tick_time: f64,
+}
+
+impl SearchLimits {
+    pub const UNLIMITED: Self = Self {
+        max_guesses: u64::MAX,
+        max_search_time: f64::INFINITY,
+        max_tick_time: f64::INFINITY,
+    };
+
+    /// `monotonic_time` the searches of a tick that starts now have to end by
+    pub fn tick_deadline(&self) -> f64 {
+        monotonic_time() + self.max_tick_time
+    }
+
+    /// Budget for a search that starts now and has to end before `tick_deadline`.
+    pub fn budget(&self, tick_deadline: f64) -> SearchBudget {
+        let deadline = (monotonic_time() + self.max_search_time).min(tick_deadline);
+        SearchBudget::new(self.max_guesses, deadline)
+    }
+}
+
+impl Default for SearchLimits {
+    fn default() -> Self {
+        Self {
+            max_guesses: 100_000_000,
+            max_search_time: 5.0,
+            max_tick_time: 10.0,
+        }
+    }
+}
+
+/// Number of guesses and time a search may use. Patterns with many unconstrained regions can
+/// need a huge number of guesses, a search is stopped once its budget is exceeded.
+#[derive(Debug)]
+pub struct SearchBudget {
+    max_guesses: u64,
+
+    /// `monotonic_time` the search has to end by
+    deadline: f64,
+
+    guesses: AtomicU64,
+    exceeded: AtomicBool,
+}
+
+impl SearchBudget {
+    /// The clock is only read every this many guesses
+    const CLOCK_INTERVAL: u64 = 1024;
+
+    pub fn new(max_guesses: u64, deadline: f64) -> Self {
+        Self {
+            max_guesses,
+            deadline,
+            guesses: AtomicU64::new(0),
+            exceeded: AtomicBool::new(false),
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        Self::new(u64::MAX, f64::INFINITY)
+    }
+
+    /// Pay for a single guess. Returns false if the budget is exceeded and the search has to stop.
+    pub fn spend(&self) -> bool {
+        if self.exceeded.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        let guesses = self.guesses.fetch_add(1, Ordering::Relaxed) + 1;
+        let exceeded = guesses > self.max_guesses
+            || (guesses.is_multiple_of(Self::CLOCK_INTERVAL) && monotonic_time() > self.deadline);
+        if exceeded {
+            self.exceeded.store(true, Ordering::Relaxed);
+        }
+        !exceeded
+    }
+
+    /// The search was stopped before all solutions were found.
+    pub fn is_exceeded(&self) -> bool {
+        self.exceeded.load(Ordering::Relaxed)
+    }
+
+    pub fn guesses(&self) -> u64 {
+        self.guesses.load(Ordering::Relaxed)
     }
 }
 
@@ -117,19 +235,24 @@ pub trait GuessChooser {
         &self,
         free: &HashSet<Element>,
         _assigned: &HashSet<Element>,
-        _dom: &Topology,
+        dom: &Topology,
     ) -> Option<Guess> {
         // TODO: Return a free solid region, if possible
 
         for free_variable in free {
             if let &Element::Region(region_key) = free_variable {
-                return Some(Guess::Region(region_key));
+                return Some(Guess::Region(region_key, dom[region_key].material));
             }
         }
 
         None
     }
 
+    /// Statistics of the codomain the guesses are optimized for, if any.
+    fn statistics(&self) -> Option<&TopologyStatistics> {
+        None
+    }
+
     /// Choose a free variable to guess we guess seams if possible, then borders, then regions.
     fn choose(
         &self,
@@ -179,14 +302,17 @@ impl GuessChooser for GuessChooserUsingStatistics {
 
         let lowest_material_count = free_region_vars.min_by_key(|region_key| {
             let region = &dom.regions[region_key];
-            self.statistics
-                .material_counts
-                .get(&region.material)
-                .copied()
-                .unwrap_or(0)
+            self.statistics.matching_count(region.material)
         })?;
 
-        Some(Guess::Region(lowest_material_count))
+        Some(Guess::Region(
+            lowest_material_count,
+            dom[lowest_material_count].material,
+        ))
+    }
+
+    fn statistics(&self) -> Option<&TopologyStatistics> {
+        Some(&self.statistics)
     }
 }
 
@@ -206,8 +332,8 @@ pub enum ConstraintAction {
 /// Search branching point where an assignment has to be guessed and tried.
 #[derive(Debug, Clone)]
 pub struct SearchBranch {
-    guess: Guess,
-    actions: Vec<ConstraintAction>,
+    pub guess: Guess,
+    pub actions: Vec<ConstraintAction>,
 }
 
 pub enum SearchError {
@@ -226,36 +352,46 @@ impl SearchBranch {
         tracy_span.emit_color(0xFFFF00);
 
         for action in &self.actions {
-            match action {
-                ConstraintAction::Propagation(propagation) => {
-                    // let tracy_span = tracy_client::span!("propagate");
-                    // tracy_span.emit_color(0x00FFFF);
+            action.apply(phi, codom)?;
+        }
 
-                    let derived = propagation.derives();
-                    match propagation.derive(phi, &codom.inner) {
-                        Ok(phi_derived) => {
-                            // Make sure we're not assigning hidden elements
-                            if let Element::Region(phi_derived) = phi_derived {
-                                if codom.is_hidden(phi_derived) {
-                                    return Err(SearchError::PropagationFailed);
-                                }
-                            }
+        Ok(())
+    }
+}
 
-                            phi.insert(derived, phi_derived);
-                        }
-                        Err(_err) => {
-                            // println!("Propagation {propagation:?} failed");
-                            return Err(SearchError::PropagationFailed);
+impl ConstraintAction {
+    /// Extend `phi` by the propagated element or check the constraint.
+    #[inline]
+    pub fn apply(&self, phi: &mut Morphism, codom: &MaskedTopology) -> Result<(), SearchError> {
+        match self {
+            ConstraintAction::Propagation(propagation) => {
+                // let tracy_span = tracy_client::span!("propagate");
+                // tracy_span.emit_color(0x00FFFF);
+
+                let derived = propagation.derives();
+                match propagation.derive(phi, &codom.inner) {
+                    Ok(phi_derived) => {
+                        // Make sure we're not assigning hidden elements
+                        if let Element::Region(phi_derived) = phi_derived {
+                            if codom.is_hidden(phi_derived) {
+                                return Err(SearchError::PropagationFailed);
+                            }
                         }
+
+                        phi.insert(derived, phi_derived);
+                    }
+                    Err(_err) => {
+                        // println!("Propagation {propagation:?} failed");
+                        return Err(SearchError::PropagationFailed);
                     }
                 }
-                ConstraintAction::Constraint(constraint) => {
-                    // let tracy_span = tracy_client::span!("check_constraint");
-                    // tracy_span.emit_color(0x00FFFF);
+            }
+            ConstraintAction::Constraint(constraint) => {
+                // let tracy_span = tracy_client::span!("check_constraint");
+                // tracy_span.emit_color(0x00FFFF);
 
-                    if !constraint.is_satisfied(phi, codom.inner) {
-                        return Err(SearchError::ConstraintConflict);
-                    }
+                if !constraint.is_satisfied(phi, codom.inner) {
+                    return Err(SearchError::ConstraintConflict);
                 }
             }
         }
@@ -523,53 +659,75 @@ impl SearchPlan {
     // }
 
     #[inline(never)]
-    pub fn search_step(
+    pub fn search_step<B>(
         &self,
         i_step: usize,
         phi: &mut Morphism,
         codom: &MaskedTopology,
-        found: &mut impl FnMut(&Morphism),
-    ) {
+        budget: &SearchBudget,
+        found: &mut impl FnMut(&Morphism) -> ControlFlow<B>,
+    ) -> ControlFlow<B> {
         let tracy_span = tracy_client::span!("search_step");
         tracy_span.emit_color(0xFFFF00);
 
         if i_step >= self.steps.len() {
-            found(phi);
-            return;
+            return found(phi);
         }
 
         let step = &self.steps[i_step];
 
-        step.guess.guess(phi, codom, |phi| {
+        step.guess.guess(phi, codom, budget, |phi| {
             if step.propagate_and_check_constraints(phi, codom).is_err() {
-                return;
+                return ControlFlow::Continue(());
             }
 
-            self.search_step(i_step + 1, phi, codom, found);
+            self.search_step(i_step + 1, phi, codom, budget, found)
         })
     }
 
+    /// Call `found` for each solution until it breaks, the search stops immediately in that case.
+    /// It also stops when `budget` is exceeded.
     #[inline(never)]
-    pub fn search(&self, codom: &MaskedTopology, mut found: impl FnMut(&Morphism)) {
+    pub fn try_search<B>(
+        &self,
+        codom: &MaskedTopology,
+        budget: &SearchBudget,
+        mut found: impl FnMut(&Morphism) -> ControlFlow<B>,
+    ) -> ControlFlow<B> {
         let mut phi = Morphism::new();
-        self.search_step(0, &mut phi, codom, &mut found);
+        self.search_step(0, &mut phi, codom, budget, &mut found)
     }
 
+    /// Call `found` for each solution, without limiting the search.
+    pub fn search(&self, codom: &MaskedTopology, mut found: impl FnMut(&Morphism)) {
+        let _ = self.try_search(codom, &SearchBudget::unlimited(), |phi| {
+            found(phi);
+            ControlFlow::<()>::Continue(())
+        });
+    }
+
+    /// Same as `try_search` but the first guess is fixed to `phi_region_key`.
     #[inline(never)]
-    pub fn search_with_first_guessed(
+    pub fn try_search_with_first_guessed<B>(
         &self,
         codom: &MaskedTopology,
         phi_region_key: RegionKey,
-        mut found: impl FnMut(&Morphism),
-    ) {
+        budget: &SearchBudget,
+        mut found: impl FnMut(&Morphism) -> ControlFlow<B>,
+    ) -> ControlFlow<B> {
         let tracy_span = tracy_client::span!("search_with_first_guessed");
         tracy_span.emit_color(0xFFFF00);
 
         let first_step = self.steps.first().unwrap();
 
+        // The fixed first guess is paid like any other
+        if !budget.spend() {
+            return ControlFlow::Continue(());
+        }
+
         // Create Morphism with first guess assigned
         let mut phi = Morphism::new();
-        let Guess::Region(region_key) = first_step.guess else {
+        let Guess::Region(region_key, _) = first_step.guess else {
             panic!("First guess must be region");
         };
         phi.region_map.insert(region_key, phi_region_key);
@@ -578,10 +736,21 @@ impl SearchPlan {
             .propagate_and_check_constraints(&mut phi, codom)
             .is_err()
         {
-            return;
+            return ControlFlow::Continue(());
         }
 
-        self.search_step(1, &mut phi, codom, &mut found);
+        self.search_step(1, &mut phi, codom, budget, &mut found)
+    }
+
+    /// Number of region guesses after the first one. Each of them tries all regions of the
+    /// codomain with a matching material, so the search time grows with the number of regions to
+    /// the power of this plus one.
+    pub fn unconstrained_guesses(&self) -> usize {
+        self.steps
+            .iter()
+            .skip(1)
+            .filter(|step| matches!(step.guess, Guess::Region(..)))
+            .count()
     }
 
     #[inline(never)]
@@ -621,6 +790,10 @@ impl SearchPlan {
 pub struct SearchStrategy {
     pub plans: Vec<(Material, SearchPlan)>,
     pub main_plan: SearchPlan,
+
+    /// Number of codomain regions matching each material of the pattern that the plans were
+    /// optimized for. None if the plans don't depend on the codomain.
+    pub planned_counts: Option<Vec<(Material, usize)>>,
 }
 
 impl SearchStrategy {
@@ -632,7 +805,7 @@ impl SearchStrategy {
         let mut plans = Vec::new();
         for region_key in dom.iter_region_keys() {
             let material = dom[region_key].material;
-            let first_guess = Guess::Region(region_key);
+            let first_guess = Guess::Region(region_key, material);
 
             let plan = SearchPlan::new(
                 constraint_system.clone(),
@@ -645,61 +818,139 @@ impl SearchStrategy {
 
         let main_plan = SearchPlan::new(constraint_system, dom, guess_chooser, None);
 
-        Self { plans, main_plan }
+        let planned_counts = guess_chooser.statistics().map(|statistics| {
+            dom.iter_region_values()
+                .map(|region| region.material)
+                .unique()
+                .map(|material| (material, statistics.matching_count(material)))
+                .collect()
+        });
+
+        Self {
+            plans,
+            main_plan,
+            planned_counts,
+        }
     }
 
-    /// Find all solutions `phi` where the image of `phi` contains `region_key`
+    /// A material count has to change by at least this factor for the plans to be outdated.
+    const REPLAN_FACTOR: usize = 4;
+
+    /// Are the plans optimized for material counts that differ a lot from `live`, for example
+    /// because a material that was rare when the program was compiled became common.
+    pub fn needs_replan(&self, live: &TopologyStatistics) -> bool {
+        let Some(planned_counts) = &self.planned_counts else {
+            return false;
+        };
+
+        planned_counts.iter().any(|&(material, planned_count)| {
+            let live_count = live.matching_count(material);
+            let (low, high) = if planned_count < live_count {
+                (planned_count, live_count)
+            } else {
+                (live_count, planned_count)
+            };
+            high + 1 >= Self::REPLAN_FACTOR * (low + 1)
+        })
+    }
+
+    /// Make new plans for the pattern `dom` that are optimized for `live`.
+    pub fn replan(&mut self, dom: &Topology, live: &TopologyStatistics) {
+        let _tracy_span = tracy_client::span!("SearchStrategy::replan");
+        *self = Self::for_morphism(dom, &GuessChooserUsingStatistics::new(live.clone()));
+    }
+
+    /// Find all solutions `phi` where the image of `phi` contains `contained`. If `budget` is
+    /// exceeded only some of the solutions are returned.
     #[inline(never)]
-    pub fn solutions(&self, codom: &MaskedTopology, contained: Option<RegionKey>) -> Vec<Morphism> {
+    pub fn solutions(
+        &self,
+        codom: &MaskedTopology,
+        contained: Option<RegionKey>,
+        budget: &SearchBudget,
+    ) -> Vec<Morphism> {
         let _span = tracy_client::span!("SearchStrategy::solutions");
 
         let mut solutions = Vec::new();
-        // Without the explicit type annotation of the lambda fails to compile, weird. Maybe the
-        // lifetime it derives for phi is wrong.
-        let mut on_solution_found = |phi: &Morphism| {
+        let _ = self.try_search(codom, contained, budget, |phi| {
             for &phi_region_key in phi.region_map.values() {
                 assert!(!codom.is_hidden(phi_region_key));
             }
 
             solutions.push(phi.clone());
+            ControlFlow::<()>::Continue(())
+        });
+        solutions
+    }
+
+    /// Call `found` for each solution `phi` where the image of `phi` contains `contained` until it
+    /// breaks. The solutions come in the same order as from `solutions`.
+    pub fn try_search<B>(
+        &self,
+        codom: &MaskedTopology,
+        contained: Option<RegionKey>,
+        budget: &SearchBudget,
+        mut found: impl FnMut(&Morphism) -> ControlFlow<B>,
+    ) -> ControlFlow<B> {
+        let _span = tracy_client::span!("SearchStrategy::try_search");
+
+        let Some(contained) = contained else {
+            return self.main_plan.try_search(codom, budget, found);
         };
 
-        if let Some(contained) = contained {
-            // If `contained` is hidden there are no solutions
-            if codom.is_hidden(contained) {
-                return Vec::new();
+        // If `contained` is hidden there are no solutions
+        if codom.is_hidden(contained) {
+            return ControlFlow::Continue(());
+        }
+
+        let region = &codom.inner[contained];
+        for (first_material, plan) in &self.plans {
+            if first_material.matches(region.material) {
+                plan.try_search_with_first_guessed(codom, contained, budget, &mut found)?;
             }
+        }
 
-            let region = &codom.inner[contained];
-            for pair in &self.plans {
-                let (first_material, plan) = pair;
-                if first_material.matches(region.material) {
-                    plan.search_with_first_guessed(codom, contained, &mut on_solution_found)
-                }
+        ControlFlow::Continue(())
+    }
+
+    /// First solution in the order of `solutions` that is accepted by `accept`.
+    pub fn find(
+        &self,
+        codom: &MaskedTopology,
+        contained: Option<RegionKey>,
+        budget: &SearchBudget,
+        mut accept: impl FnMut(&Morphism) -> bool,
+    ) -> Option<Morphism> {
+        let found = self.try_search(codom, contained, budget, |phi| {
+            if accept(phi) {
+                ControlFlow::Break(phi.clone())
+            } else {
+                ControlFlow::Continue(())
             }
-        } else {
-            // self.main_plan.search(codom, |phi| {
+        });
 
-            // })
-            self.main_plan.search(codom, &mut on_solution_found)
+        match found {
+            ControlFlow::Break(phi) => Some(phi),
+            ControlFlow::Continue(()) => None,
         }
-
-        solutions
     }
 }
 
 #[cfg(test)]
 mod test {
     use crate::{
+        compiler::Compiler,
         field::RgbaField,
         material::Material,
-        math::rgba8::Rgba8,
+        math::rgba8::{Rgb, Rgba8},
         pixmap::MaterialMap,
-        solver::plan::{ConstraintSystem, SearchPlan, SimpleGuessChooser},
-        topology::{MaskedTopology, Topology},
+        solver::plan::{ConstraintSystem, SearchBudget, SearchPlan, SimpleGuessChooser},
+        topology::{MaskedTopology, Topology, TopologyStatistics},
+        world::World,
     };
+    use ahash::HashSet;
     use itertools::Itertools;
-    use std::path::Path;
+    use std::{ops::ControlFlow, path::Path};
 
     fn load(path: impl AsRef<Path>) -> Topology {
         let rgba_field = RgbaField::load(path).unwrap();
@@ -782,6 +1033,127 @@ mod test {
         assert_eq!(solutions.len(), expected_solutions_len);
     }
 
+    #[test]
+    fn unconstrained_guesses() {
+        let plan = |name: &str| {
+            let dom = load(format!("test_resources/patterns/{name}/pattern.png"));
+            let constraint_system = ConstraintSystem::for_morphism(&dom);
+            SearchPlan::new(
+                constraint_system,
+                &dom,
+                &SimpleGuessChooser::default(),
+                None,
+            )
+        };
+        assert_eq!(plan("a").unconstrained_guesses(), 0);
+        assert_eq!(plan("two_holes_a").unconstrained_guesses(), 0);
+        // The second part can be anywhere
+        assert_eq!(plan("disjoint").unconstrained_guesses(), 1);
+    }
+
+    /// A search that exceeds its budget stops and finds only part of the solutions.
+    #[test]
+    fn budget_exceeded() {
+        let dom = load("test_resources/patterns/disjoint/pattern.png");
+        let codom = load("test_resources/patterns/disjoint/match_3.png");
+        let codom = MaskedTopology::whole(&codom);
+        let constraint_system = ConstraintSystem::for_morphism(&dom);
+        let plan = SearchPlan::new(
+            constraint_system,
+            &dom,
+            &SimpleGuessChooser::default(),
+            None,
+        );
+
+        let count_solutions = |budget: &SearchBudget| {
+            let mut count = 0;
+            let _ = plan.try_search(&codom, budget, |_| {
+                count += 1;
+                ControlFlow::<()>::Continue(())
+            });
+            count
+        };
+
+        let unlimited = SearchBudget::unlimited();
+        assert_eq!(count_solutions(&unlimited), 4);
+        assert!(!unlimited.is_exceeded());
+
+        let budget = SearchBudget::new(unlimited.guesses() - 1, f64::INFINITY);
+        assert!(count_solutions(&budget) < 4);
+        assert!(budget.is_exceeded());
+    }
+
+    /// `find` returns the first accepted solution in the order of `solutions` and stops searching
+    /// right after it.
+    #[test]
+    fn find_stops_early() {
+        let world =
+            World::load("test_resources/benchmark/cellular_automaton_triangles.png").unwrap();
+        let program = Compiler::new().compile(&world).unwrap();
+        let codom = MaskedTopology::new(world.topology(), &program.source);
+
+        for (_, instance) in program.iter_rule_instances() {
+            let search_strategy = &instance.rule.before.search_strategy;
+            let solutions = search_strategy.solutions(&codom, None, &SearchBudget::unlimited());
+
+            let mut visited = 0;
+            let second = search_strategy.find(&codom, None, &SearchBudget::unlimited(), |_| {
+                visited += 1;
+                visited == 2
+            });
+            assert_eq!(second.as_ref(), solutions.get(1));
+            assert_eq!(visited, solutions.len().min(2));
+
+            // Same with a contained region
+            let Some(contained) = solutions
+                .first()
+                .and_then(|phi| phi.region_map.values().next())
+            else {
+                continue;
+            };
+            let contained_solutions =
+                search_strategy.solutions(&codom, Some(*contained), &SearchBudget::unlimited());
+            let first =
+                search_strategy.find(&codom, Some(*contained), &SearchBudget::unlimited(), |_| {
+                    true
+                });
+            assert_eq!(first.as_ref(), contained_solutions.first());
+        }
+    }
+
+    /// Plans are outdated once the material counts differ enough from the ones they were compiled
+    /// for, new plans find the same solutions.
+    #[test]
+    fn replan() {
+        let world = World::load("test_resources/compiler/basic_1/world.png").unwrap();
+        let program = Compiler::new().compile(&world).unwrap();
+        let pattern = &program.rules[0].instances[0].rule.before;
+        let mut live = TopologyStatistics::new(&MaskedTopology::whole(world.topology()));
+        assert!(!pattern.search_strategy.needs_replan(&live));
+
+        // Many more yellow squares, like the one in the rule
+        let yellow = Material::normal(Rgb(0xFF, 0xEC, 0x27));
+        *live.material_counts.get_mut(&yellow).unwrap() += 100;
+        assert!(pattern.search_strategy.needs_replan(&live));
+
+        let mut search_strategy = pattern.search_strategy.clone();
+        search_strategy.replan(&pattern.topology, &live);
+        assert!(!search_strategy.needs_replan(&live));
+
+        let codom = MaskedTopology::new(world.topology(), &program.source);
+        let solutions: HashSet<_> = pattern
+            .search_strategy
+            .solutions(&codom, None, &SearchBudget::unlimited())
+            .into_iter()
+            .collect();
+        let replanned_solutions: HashSet<_> = search_strategy
+            .solutions(&codom, None, &SearchBudget::unlimited())
+            .into_iter()
+            .collect();
+        assert_eq!(solutions.len(), 1);
+        assert_eq!(solutions, replanned_solutions);
+    }
+
     #[test]
     fn extract_pattern_a() {
         assert_extract_inner_outer("a");
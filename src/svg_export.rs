@@ -0,0 +1,181 @@
+//! Vector export of a `Topology`. Each region becomes a path with its holes (evenodd fill rule),
+//! optionally with the seams drawn as lines and the corners between seams as dots. Coordinates
+//! are pixel coordinates, one unit per pixel.
+
+use crate::{
+    material::MaterialClass,
+    math::{
+        pixel::{Corner, CornerName, Side},
+        point::Point,
+    },
+    painting::nice_line_painter::NiceLineGeometry,
+    topology::{Border, Topology},
+};
+use itertools::Itertools;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SvgOptions {
+    pub seams: bool,
+    pub corners: bool,
+}
+
+const SEAM_STROKE: &str = r##"fill="none" stroke="#000000" stroke-width="0.15" stroke-linecap="round" stroke-linejoin="round""##;
+const CORNER_RADIUS: f64 = 0.25;
+const CORNER_FILL: &str = "#e02020";
+
+pub fn topology_svg(topology: &Topology, options: SvgOptions) -> String {
+    let bounds = topology.bounding_rect();
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}" width="{}" height="{}">"#,
+        bounds.left(),
+        bounds.top(),
+        bounds.width(),
+        bounds.height(),
+        bounds.width(),
+        bounds.height()
+    )
+    .unwrap();
+
+    // Transparent regions are not drawn, the holes of the regions around them show through.
+    let regions = topology
+        .iter_region_values()
+        .filter(|region| region.material.class != MaterialClass::Transparent);
+
+    writeln!(svg, r#"<g fill-rule="evenodd">"#).unwrap();
+    for region in regions.clone() {
+        let path = region
+            .boundary
+            .borders
+            .iter()
+            .map(|border| format!("{}Z", polyline(&border_points(&border.sides))))
+            .join(" ");
+        let rgb = region.material.rgb;
+        writeln!(
+            svg,
+            r##"<path d="{path}" fill="#{:02x}{:02x}{:02x}"/>"##,
+            rgb.r, rgb.g, rgb.b
+        )
+        .unwrap();
+    }
+    writeln!(svg, "</g>").unwrap();
+
+    let borders = || {
+        regions
+            .clone()
+            .flat_map(|region| region.boundary.borders.iter())
+    };
+
+    if options.seams {
+        writeln!(svg, "<g {SEAM_STROKE}>").unwrap();
+        for border in borders() {
+            for sides in seam_sides(border) {
+                let points = border_points(&sides);
+                if points.len() >= 2 {
+                    writeln!(svg, r#"<path d="{}"/>"#, polyline(&points)).unwrap();
+                }
+            }
+        }
+        writeln!(svg, "</g>").unwrap();
+    }
+
+    if options.corners {
+        writeln!(svg, r#"<g fill="{CORNER_FILL}">"#).unwrap();
+        let corners = borders().flat_map(|border| border.corners()).unique();
+        for corner in corners {
+            let Point { x, y } = corner_point(corner);
+            writeln!(svg, r#"<circle cx="{x}" cy="{y}" r="{CORNER_RADIUS}"/>"#).unwrap();
+        }
+        writeln!(svg, "</g>").unwrap();
+    }
+
+    writeln!(svg, "</svg>").unwrap();
+    svg
+}
+
+/// Sides of each atomic seam of `border`, the whole border if it has a single seam.
+fn seam_sides(border: &Border) -> impl Iterator<Item = Vec<Side>> + '_ {
+    border
+        .cycle_segments
+        .iter()
+        .map(|segment| segment.iter().map(|i| border.sides[i]).collect())
+}
+
+/// Start and stop points of the sides, without points in the middle of straight lines. Diagonal
+/// sides have no length and are skipped.
+fn border_points(sides: &[Side]) -> Vec<Point<i64>> {
+    let arrows = sides
+        .iter()
+        .filter_map(|&side| NiceLineGeometry::arrow_from_side(side));
+
+    let mut points: Vec<Point<i64>> = Vec::new();
+    for arrow in arrows {
+        if points.last() != Some(&arrow.a) {
+            points.push(arrow.a);
+        }
+        if let [.., before, last] = points[..] {
+            let collinear =
+                (last - before).x * (arrow.b - last).y == (last - before).y * (arrow.b - last).x;
+            if collinear {
+                points.pop();
+            }
+        }
+        points.push(arrow.b);
+    }
+    points
+}
+
+fn polyline(points: &[Point<i64>]) -> String {
+    points
+        .iter()
+        .enumerate()
+        .map(|(i, point)| {
+            let command = if i == 0 { "M" } else { "L" };
+            format!("{command}{} {}", point.x, point.y)
+        })
+        .join(" ")
+}
+
+/// Corners at the same grid point, that are only separated by a diagonal side, are at the same
+/// position.
+fn corner_point(corner: Corner) -> Point<i64> {
+    let Point { x, y } = corner.pixel;
+    match corner.name {
+        CornerName::TopStart => Point(x + 1, y),
+        CornerName::TopStop => Point(x, y),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        material::MaterialClass,
+        svg_export::{SvgOptions, topology_svg},
+        world::World,
+    };
+
+    #[test]
+    fn basic_1() {
+        let world = World::load("test_resources/compiler/basic_1/world.png").unwrap();
+        let topology = world.topology();
+        let drawn_regions = topology
+            .iter_region_values()
+            .filter(|region| region.material.class != MaterialClass::Transparent)
+            .count();
+
+        let svg = topology_svg(topology, SvgOptions::default());
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches("<path").count(), drawn_regions);
+        assert!(!svg.contains("<circle"));
+
+        let options = SvgOptions {
+            seams: true,
+            corners: true,
+        };
+        let svg = topology_svg(topology, options);
+        assert!(svg.matches("<path").count() > drawn_regions);
+        assert!(svg.contains("<circle"));
+    }
+}
@@ -0,0 +1,93 @@
+//! Images on the clipboard of the operating system. egui only pastes text and copies images as
+//! premultiplied `Color32`, which changes the rgb of pixels with alphas other than 0 and 255, for
+//! example rule interiors. So the native app copies and pastes straight rgba with arboard
+//! directly. The web app can only paste text, there images are copied as base64 pngs.
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{
+    field::RgbaField,
+    math::{point::Point, rect::Rect, rgba8::Rgba8},
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{borrow::Cow, cell::RefCell};
+
+#[cfg(not(target_arch = "wasm32"))]
+thread_local! {
+    /// Kept alive, on Linux the copied image is only served while the clipboard exists.
+    static CLIPBOARD: RefCell<Option<arboard::Clipboard>> = const { RefCell::new(None) };
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn with_clipboard<T>(
+    f: impl FnOnce(&mut arboard::Clipboard) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    CLIPBOARD.with_borrow_mut(|clipboard| {
+        if clipboard.is_none() {
+            *clipboard = Some(arboard::Clipboard::new()?);
+        }
+        f(clipboard.as_mut().unwrap())
+    })
+}
+
+/// Straight, not premultiplied, rgba
+#[cfg(not(target_arch = "wasm32"))]
+pub fn image_data(rgba_field: &RgbaField) -> arboard::ImageData<'_> {
+    arboard::ImageData {
+        width: rgba_field.width() as usize,
+        height: rgba_field.height() as usize,
+        bytes: Cow::Borrowed(rgba_field.as_raw()),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn from_image_data(image: &arboard::ImageData) -> RgbaField {
+    let size = Point(image.width as i64, image.height as i64);
+    let pixels: &[Rgba8] = bytemuck::cast_slice(&image.bytes);
+    RgbaField::from_linear(Rect::low_size(Point::ZERO, size), pixels.to_vec())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn set_image(rgba_field: &RgbaField) -> anyhow::Result<()> {
+    with_clipboard(|clipboard| Ok(clipboard.set_image(image_data(rgba_field))?))
+}
+
+/// Image on the system clipboard, or a base64 png copied as text. `None` if the clipboard
+/// contains neither.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn get_image() -> anyhow::Result<Option<RgbaField>> {
+    with_clipboard(|clipboard| match clipboard.get_image() {
+        Ok(image) => Ok(Some(from_image_data(&image))),
+        Err(arboard::Error::ContentNotAvailable) => {
+            let text = clipboard.get_text().unwrap_or_default();
+            Ok(RgbaField::decode_base64_png(&text).ok())
+        }
+        Err(err) => Err(err.into()),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        image_import::{UnknownAlpha, import_image},
+        material_effects::material_map_effects,
+        math::rgba8::Rgba8,
+        system_clipboard::{from_image_data, image_data},
+        world::World,
+    };
+
+    /// Copy and paste of a world with rule frames, without the system clipboard
+    #[test]
+    fn rule_round_trip() {
+        let world = World::load("test_resources/compiler/basic_1/world.png").unwrap();
+        let material_map = world.material_map();
+        assert!(material_map.values().any(|material| material.is_rule()));
+
+        let copied = material_map_effects(material_map, Rgba8::TRANSPARENT);
+        let pasted = from_image_data(&image_data(&copied));
+        assert_eq!(pasted, copied);
+
+        let (pasted_map, summary) = import_image(&pasted, UnknownAlpha::Reject, None).unwrap();
+        assert!(summary.is_lossless());
+        assert_eq!(&pasted_map, material_map);
+    }
+}
@@ -16,7 +16,7 @@ use crate::{
     utils::{UndirectedEdge, UndirectedGraph},
 };
 use ahash::{HashMap, HashSet};
-use itertools::Itertools;
+use itertools::{Either, Itertools};
 use std::{
     collections::{BTreeMap, BTreeSet},
     fmt,
@@ -25,7 +25,7 @@ use std::{
     iter,
     ops::{
         Bound::{Excluded, Unbounded},
-        Index, IndexMut,
+        Index,
     },
     path::Path,
     sync::atomic::{AtomicI64, Ordering},
@@ -104,12 +104,18 @@ impl Display for Seam {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct SeamMaterials {
     pub left: Material,
     pub right: Option<Material>,
 }
 
+impl SeamMaterials {
+    pub fn new(left: Material, right: Option<Material>) -> Self {
+        Self { left, right }
+    }
+}
+
 // #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
 // pub struct RegionKey {
 //     key: usize,
@@ -442,6 +448,8 @@ impl BorderKey {
 #[derive(Debug, Clone)]
 pub struct Topology {
     // TODO: Make Vec<Region>
+    /// Must only be mutated by the methods of Topology, changing the material of a region
+    /// directly would bypass `material_regions` and `seam_counts`.
     pub regions: BTreeMap<RegionKey, Region>,
 
     pub cycle_groups: ConnectedCycleGroups,
@@ -456,6 +464,13 @@ pub struct Topology {
     /// Mapping the modification time of each region to the region. Each region is contained
     /// exactly once!
     modifications: BTreeMap<AtomicTime, RegionKey>,
+
+    /// Keys of the regions of each material, materials without regions are not contained.
+    material_regions: HashMap<Material, BTreeSet<RegionKey>>,
+
+    /// Number of atomic seams for each pair of left and right material. A seam between two regions
+    /// is counted from both sides.
+    seam_counts: HashMap<SeamMaterials, usize>,
 }
 
 impl Topology {
@@ -507,14 +522,23 @@ impl Topology {
             }
         }
 
-        Self {
+        let mut topology = Self {
             bounds: boundary_cycles.bounds,
             cycle_groups,
             boundary_cycles,
             regions,
             seam_indices,
             modifications,
+            material_regions: HashMap::default(),
+            seam_counts: HashMap::default(),
+        };
+
+        let region_keys: Vec<_> = topology.regions.keys().copied().collect();
+        for region_key in region_keys {
+            topology.index_region(region_key);
         }
+
+        topology
     }
 
     #[inline(never)]
@@ -716,6 +740,8 @@ impl Topology {
             return false;
         }
 
+        self.set_region_material_unchecked(region_key, material);
+
         let region = self.regions.get_mut(&region_key).unwrap();
         self.modifications.remove(&region.modified_time);
         let modified_time = atomic_time_counter();
@@ -724,21 +750,125 @@ impl Topology {
         //     region_key, region.modified_time, modified_time
         // );
         region.modified_time = modified_time;
-        region.material = material;
         self.modifications.insert(modified_time, region_key);
 
         true
     }
 
-    pub fn material_seam_graph(&self) -> UndirectedGraph<Option<Material>> {
-        let mut edges = BTreeSet::new();
-        for seam in self.iter_seams() {
-            let seam_materials = self.seam_materials(seam);
-            let edge = UndirectedEdge::new(Some(seam_materials.left), seam_materials.right);
-            edges.insert(edge);
+    /// Set the material of `region_key` even if the region collapses with a neighbor, the caller
+    /// has to update the Topology from the material map afterwards in that case. Does not change
+    /// the modification time.
+    pub fn set_region_material_unchecked(&mut self, region_key: RegionKey, material: Material) {
+        let previous = self.regions[&region_key].material;
+        if previous == material {
+            return;
+        }
+
+        // Seams of the region and the reversed seams of its neighbors change their materials.
+        let right_materials: Vec<_> = self.regions[&region_key]
+            .iter_seams()
+            .map(|seam| self.indexed_seam_materials(seam).right)
+            .collect();
+        for right in right_materials {
+            self.count_seam(SeamMaterials::new(previous, right), -1);
+            self.count_seam(SeamMaterials::new(material, right), 1);
+            if let Some(right) = right {
+                self.count_seam(SeamMaterials::new(right, Some(previous)), -1);
+                self.count_seam(SeamMaterials::new(right, Some(material)), 1);
+            }
+        }
+
+        self.unindex_material(previous, region_key);
+        self.material_regions
+            .entry(material)
+            .or_default()
+            .insert(region_key);
+
+        self.regions.get_mut(&region_key).unwrap().material = material;
+    }
+
+    /// Materials on both sides of an atomic seam, using the seam indices instead of the slower
+    /// `region_at`.
+    fn indexed_seam_materials(&self, seam: Seam) -> SeamMaterials {
+        let left = self.regions[&self.left_of(seam)].material;
+        let right = self
+            .right_of(seam)
+            .map(|region_key| self.regions[&region_key].material);
+        SeamMaterials::new(left, right)
+    }
+
+    fn count_seam(&mut self, seam_materials: SeamMaterials, delta: isize) {
+        let count = self.seam_counts.entry(seam_materials).or_default();
+        *count = count.checked_add_signed(delta).unwrap();
+        if *count == 0 {
+            self.seam_counts.remove(&seam_materials);
+        }
+    }
+
+    fn unindex_material(&mut self, material: Material, region_key: RegionKey) {
+        let region_keys = self.material_regions.get_mut(&material).unwrap();
+        region_keys.remove(&region_key);
+        if region_keys.is_empty() {
+            self.material_regions.remove(&material);
+        }
+    }
+
+    /// Add a region to `material_regions` and `seam_counts`. The seam indices of the region and its
+    /// neighbors must be up to date.
+    fn index_region(&mut self, region_key: RegionKey) {
+        let region = &self.regions[&region_key];
+        let seam_materials: Vec<_> = region
+            .iter_seams()
+            .map(|seam| self.indexed_seam_materials(seam))
+            .collect();
+        self.material_regions
+            .entry(region.material)
+            .or_default()
+            .insert(region_key);
+        for seam_materials in seam_materials {
+            self.count_seam(seam_materials, 1);
+        }
+    }
+
+    /// Inverse of `index_region`.
+    fn unindex_region(&mut self, region_key: RegionKey) {
+        let region = &self.regions[&region_key];
+        let material = region.material;
+        let seam_materials: Vec<_> = region
+            .iter_seams()
+            .map(|seam| self.indexed_seam_materials(seam))
+            .collect();
+        self.unindex_material(material, region_key);
+        for seam_materials in seam_materials {
+            self.count_seam(seam_materials, -1);
         }
+    }
 
-        edges
+    pub fn material_seam_graph(&self) -> UndirectedGraph<Option<Material>> {
+        self.seam_counts
+            .keys()
+            .map(|seam_materials| {
+                UndirectedEdge::new(Some(seam_materials.left), seam_materials.right)
+            })
+            .collect()
+    }
+
+    /// Number of atomic seams with the given materials on the left and right.
+    pub fn seam_count(&self, seam_materials: SeamMaterials) -> usize {
+        self.seam_counts.get(&seam_materials).copied().unwrap_or(0)
+    }
+
+    /// Number of regions of each material that exists in the Topology.
+    pub fn material_counts(&self) -> impl Iterator<Item = (Material, usize)> + '_ {
+        self.material_regions
+            .iter()
+            .map(|(&material, region_keys)| (material, region_keys.len()))
+    }
+
+    pub fn material_count(&self, material: Material) -> usize {
+        self.material_regions
+            .get(&material)
+            .map_or(0, |region_keys| region_keys.len())
     }
 
     pub fn are_seams_overlapping(&self, lhs: Seam, rhs: Seam) -> bool {
@@ -763,10 +893,20 @@ impl Topology {
         Ok(Topology::new(&material_map))
     }
 
+    pub fn region_keys_by_material(
+        &self,
+        material: Material,
+    ) -> impl Iterator<Item = RegionKey> + use<'_> {
+        self.material_regions
+            .get(&material)
+            .into_iter()
+            .flatten()
+            .copied()
+    }
+
     pub fn regions_by_material(&self, material: Material) -> impl Iterator<Item = &Region> {
-        self.regions
-            .values()
-            .filter(move |region| region.material == material)
+        self.region_keys_by_material(material)
+            .map(|region_key| &self.regions[&region_key])
     }
 
     pub fn unique_region_by_material(&self, material: Material) -> Option<&Region> {
@@ -805,36 +945,47 @@ impl Topology {
         let draw_bounds = Rect::index_bounds(pixels.clone());
         let padded_draw_bounds = draw_bounds.padded(1);
 
+        // Regions that potentially touch the draw pixels are discarded. They are removed from the
+        // material and seam indices first, while the seams of their neighbors are still known.
+        let discarded_regions: Vec<_> = self
+            .regions
+            .iter()
+            .filter(|(_, region)| region.bounds().intersects(padded_draw_bounds))
+            .map(|(&region_key, _)| region_key)
+            .collect();
+        for &region_key in &discarded_regions {
+            self.unindex_region(region_key);
+        }
+
         let touched_cycles = self.boundary_cycles.update(material_map, pixels);
 
         let mut borders = HashMap::default();
 
-        // Discard regions (including `self.seam_indices`) that potentially touch the draw pixels.
-        self.regions.retain(|_, region| {
-            let discard = region.bounds().intersects(padded_draw_bounds);
-            if discard {
-                // Remove seam indices of region
-                for seam in region.iter_seams() {
-                    self.seam_indices.remove(&seam.start);
-                }
+        // Discard regions (including `self.seam_indices`)
+        for region_key in &discarded_regions {
+            let mut region = self.regions.remove(region_key).unwrap();
 
-                // Remove from modifications
-                self.modifications.remove(&region.modified_time);
+            // Remove seam indices of region
+            for seam in region.iter_seams() {
+                self.seam_indices.remove(&seam.start);
+            }
 
-                // Try to recycle borders of discarded regions, that are touched by the draw pixels.
-                for border in region.boundary.borders.drain(..) {
-                    let cycle_min_side = border.min_side();
-                    if !touched_cycles.contains(&cycle_min_side) {
-                        borders.insert(cycle_min_side, border);
-                    }
+            // Remove from modifications
+            self.modifications.remove(&region.modified_time);
+
+            // Try to recycle borders of discarded regions, that are touched by the draw pixels.
+            for border in region.boundary.borders.drain(..) {
+                let cycle_min_side = border.min_side();
+                if !touched_cycles.contains(&cycle_min_side) {
+                    borders.insert(cycle_min_side, border);
                 }
             }
-            !discard
-        });
+        }
 
-        // TODO: This is currently the biggest performance bottleneck. We should only recompute
-        //   groups where necessary.
-        self.cycle_groups = ConnectedCycleGroups::from_cycles(&self.boundary_cycles);
+        // Only the groups of discarded regions can change
+        let new_groups =
+            self.cycle_groups
+                .update(&self.boundary_cycles, discarded_regions, &touched_cycles);
 
         // Create a new Border for each cycle if it wasn't recycled
         for cycle_min_side in touched_cycles {
@@ -844,17 +995,10 @@ impl Topology {
                 .or_insert_with(|| Border::from_cycle(cycle, &self.boundary_cycles));
         }
 
-        // Create Regions from cycle_groups
-        for cycle_group in self.cycle_groups.groups() {
-            let region_key = cycle_group.outer_cycle_min_side();
-
-            if self
-                .regions
-                .contains_key(&cycle_group.outer_cycle_min_side())
-            {
-                // We were able to keep this region
-                continue;
-            }
+        // Create Regions from the new cycle groups
+        for &region_key in &new_groups {
+            let cycle_group = &self.cycle_groups.outer_cycle_to_group[&region_key];
+            debug_assert!(!self.regions.contains_key(&region_key));
 
             let region = Region::from_cycle_group(cycle_group, material_map, &mut borders);
 
@@ -870,6 +1014,11 @@ impl Topology {
             self.regions.insert(region_key, region);
         }
 
+        // All seam indices of the new regions and their neighbors exist now
+        for region_key in new_groups {
+            self.index_region(region_key);
+        }
+
         // For debugging, reset all modified times
         // self.modifications.clear();
         // for (&region_key, region) in &mut self.regions {
@@ -950,12 +1099,6 @@ impl Index<RegionKey> for Topology {
     }
 }
 
-impl IndexMut<RegionKey> for Topology {
-    fn index_mut(&mut self, key: RegionKey) -> &mut Self::Output {
-        self.regions.get_mut(&key).unwrap()
-    }
-}
-
 impl Index<BorderKey> for Topology {
     type Output = Border;
 
@@ -1030,6 +1173,28 @@ impl<'a> MaskedTopology<'a> {
     pub fn visible_region_keys(&'a self) -> impl Iterator<Item = RegionKey> + Clone + use<'a> {
         self.visible_regions().map(|(key, _)| key)
     }
+
+    /// Visible regions that a pattern region with `material` can be mapped to, see
+    /// `Material::matches`. Uses the material index, wildcards return all visible regions.
+    pub fn visible_region_keys_matching(
+        &'a self,
+        material: Material,
+    ) -> impl Iterator<Item = RegionKey> + use<'a> {
+        let region_keys = if material.is_wildcard() {
+            Either::Left(self.inner.iter_region_keys())
+        } else if material.is_solid() {
+            let normal = Material::normal(material.rgb);
+            Either::Right(Either::Left(
+                self.inner
+                    .region_keys_by_material(material)
+                    .merge(self.inner.region_keys_by_material(normal)),
+            ))
+        } else {
+            Either::Right(Either::Right(self.inner.region_keys_by_material(material)))
+        };
+
+        region_keys.filter(|&region_key| !self.is_hidden(region_key))
+    }
 }
 
 impl<'a> From<&'a Topology> for MaskedTopology<'a> {
@@ -1044,15 +1209,36 @@ pub struct TopologyStatistics {
 }
 
 impl TopologyStatistics {
+    /// Uses the material index of the Topology, only the hidden regions have to be visited.
     pub fn new(topology: &MaskedTopology) -> Self {
-        let mut statistics = TopologyStatistics::default();
-        for (_, region) in topology.visible_regions() {
-            *statistics
-                .material_counts
-                .entry(region.material)
-                .or_default() += 1;
+        let mut material_counts: HashMap<Material, usize> =
+            topology.inner.material_counts().collect();
+
+        for region_key in topology.hidden.into_iter().flatten() {
+            let Some(region) = topology.inner.regions.get(region_key) else {
+                continue;
+            };
+            let count = material_counts.get_mut(&region.material).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                material_counts.remove(&region.material);
+            }
+        }
+
+        Self { material_counts }
+    }
+
+    /// Number of regions that a pattern region with `material` can be mapped to.
+    pub fn matching_count(&self, material: Material) -> usize {
+        if material.is_wildcard() || material.is_solid() {
+            self.material_counts
+                .iter()
+                .filter(|&(&other, _)| material.matches(other))
+                .map(|(_, &count)| count)
+                .sum()
+        } else {
+            self.material_counts.get(&material).copied().unwrap_or(0)
         }
-        statistics
     }
 }
 
@@ -1062,9 +1248,12 @@ pub mod test {
         material::Material,
         math::{point::Point, rect::Rect, rgba8::Rgb8},
         pixmap::MaterialMap,
-        topology::{Region, SeamIndex, Topology},
+        rule::FillRegion,
+        topology::{Region, RegionKey, SeamIndex, SeamMaterials, Topology},
         utils::{KeyValueItertools, UndirectedEdge, UndirectedGraph},
+        world::World,
     };
+    use ahash::HashMap;
     use itertools::Itertools;
     use std::{collections::BTreeSet, mem::swap};
 
@@ -1135,6 +1324,24 @@ pub mod test {
                 assert!(topology.contains_seam(seam.atom_reversed()));
             }
         }
+
+        // Material and seam indices are the same as when counted from scratch
+        let mut material_regions: HashMap<Material, BTreeSet<RegionKey>> = HashMap::default();
+        for (&region_key, region) in &topology.regions {
+            material_regions
+                .entry(region.material)
+                .or_default()
+                .insert(region_key);
+        }
+        assert_eq!(topology.material_regions, material_regions);
+
+        let mut seam_counts: HashMap<SeamMaterials, usize> = HashMap::default();
+        for seam in topology.iter_seams() {
+            *seam_counts
+                .entry(topology.seam_materials(seam))
+                .or_default() += 1;
+        }
+        assert_eq!(topology.seam_counts, seam_counts);
     }
 
     pub fn check_topology(filename: &str, expected_seam_graph: &UndirectedGraph<Option<Material>>) {
@@ -1275,11 +1482,28 @@ pub mod test {
         drawn_topology.update(&to_material_map, modified_pixels.iter().copied());
 
         check_structure(&drawn_topology);
+        check_cycle_groups(&drawn_topology, &to_topology);
 
         assert_eq!(to_topology.region_map(), drawn_topology.region_map());
         assert_eq!(to_topology, drawn_topology);
     }
 
+    /// Cycle groups that were updated incrementally are the same as the ones created from scratch
+    fn check_cycle_groups(drawn_topology: &Topology, expected_topology: &Topology) {
+        let drawn_groups = &drawn_topology.cycle_groups;
+        let expected_groups = &expected_topology.cycle_groups;
+        assert_eq!(
+            drawn_groups.cycle_to_outer_cycle,
+            expected_groups.cycle_to_outer_cycle
+        );
+        assert!(
+            drawn_groups
+                .groups()
+                .map(|group| &group.cycle_min_sides)
+                .eq(expected_groups.groups().map(|group| &group.cycle_min_sides))
+        );
+    }
+
     /// Uses transparent as None color
     fn check_draw_topology_from_files(from_filename: &str, to_filename: &str) {
         let folder = "test_resources/topology/draw";
@@ -1395,6 +1619,58 @@ pub mod test {
         }
     }
 
+    /// Same as `draw_random` but the topology is only updated and never recreated.
+    #[test]
+    fn draw_random_incremental() {
+        let materials = [Material::RED, Material::BLUE, Material::WHITE];
+        let size = 16;
+
+        let mut rng = fastrand::Rng::with_seed(7);
+
+        let mut material_map = MaterialMap::nones(Rect::low_size(Point(0, 0), Point(size, size)));
+        let mut topology = Topology::new(&material_map);
+
+        for _ in 0..100 {
+            let pixels: Vec<_> = (0..16)
+                .map(|_| Point(rng.i64(0..size), rng.i64(0..size)))
+                .collect();
+            for &pixel in &pixels {
+                material_map.set(pixel, rng.choice(materials).unwrap());
+            }
+            topology.update(&material_map, pixels.into_iter());
+
+            let expected_topology = Topology::new(&material_map);
+            check_structure(&topology);
+            check_cycle_groups(&topology, &expected_topology);
+            assert_eq!(topology, expected_topology);
+        }
+    }
+
+    /// Filling touching regions assigns temporary materials that must not leave stale entries in
+    /// the indices.
+    #[test]
+    fn fill_indices() {
+        let mut world = World::load("test_resources/topology/3a.png").unwrap();
+        check_structure(world.topology());
+
+        let red = Material::normal(Rgb8::RED);
+        let fills: Vec<_> = world
+            .topology()
+            .iter_regions()
+            .map(|(region_key, region)| {
+                let material = if region.material == red {
+                    Material::normal(Rgb8::BLUE)
+                } else {
+                    red
+                };
+                FillRegion::new(region_key, material)
+            })
+            .collect();
+        world.fill_regions(fills);
+        check_structure(world.topology());
+        assert_eq!(world.topology(), &Topology::new(world.material_map()));
+    }
+
     fn unique_region_by_color(topology: &Topology, rgb: Rgb8) -> &Region {
         let material = Material::normal(rgb);
         topology.unique_region_by_material(material).unwrap()
@@ -86,3 +86,25 @@ pub fn monotonic_time() -> f64 {
     let &start = START.get_or_init(|| Instant::now());
     (Instant::now() - start).as_secs_f64()
 }
+
+/// Map `items` with `f`, in parallel on the global rayon thread pool if there are at least
+/// `min_items` items. The results are returned in the same order as a sequential map would return
+/// them. Nested calls share the pool. Falls back to a sequential map on targets without threads.
+pub fn parallel_map<T: Send, R: Send>(
+    items: Vec<T>,
+    min_items: usize,
+    f: impl Fn(T) -> R + Sync + Send,
+) -> Vec<R> {
+    #[cfg(not(target_arch = "wasm32"))]
+    if items.len() >= min_items.max(2) && rayon::current_num_threads() > 1 {
+        use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+        let _tracy_span = tracy_client::span!("parallel_map");
+        return items.into_par_iter().map(f).collect();
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    let _ = min_items;
+
+    items.into_iter().map(f).collect()
+}
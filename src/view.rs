@@ -117,8 +117,8 @@ pub struct ViewSettings {
     pub edit_mode: EditMode,
     pub brush: Brush,
 
-    /// No editing allowed
-    pub locked: bool,
+    /// Index into `Palette::palettes` of the palette the brush color is chosen from
+    pub palette: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -304,6 +304,13 @@ impl View {
         self.camera = Camera::fit_world_into_view(world_bounds.cwise_as(), view_rect).round();
     }
 
+    /// Zoom and move the camera so `world_rect` is centered with some margin around it.
+    pub fn focus_camera(&mut self, world_rect: Rect<i64>, view_rect: Rect<f64>) {
+        let margin = world_rect.width().max(world_rect.height()) / 4 + 1;
+        self.camera =
+            Camera::fit_world_into_view(world_rect.padded(margin).cwise_as(), view_rect).round();
+    }
+
     pub fn zoom_in(&mut self, view_point: Point<f64>) {
         self.camera = self.camera.zoom_in_at_view_point(view_point);
     }
@@ -486,11 +493,7 @@ impl View {
             return UiState::MoveCamera(move_camera);
         }
 
-        // Only moving camera allowed when locked
-        if settings.locked {
-            return self.ui_state.clone();
-        }
-
+        // Editing is allowed while running, the program is recompiled when rules change.
         match settings.edit_mode {
             EditMode::Pointer => {}
             EditMode::Brush | EditMode::Eraser => {
@@ -1,4 +1,5 @@
 use crate::{
+    aseprite,
     brush::Brush,
     field::RgbaField,
     material::{Material, MaterialClass},
@@ -10,6 +11,7 @@ use crate::{
     },
     palettes::Palette,
     pixmap::MaterialMap,
+    project,
     rule::InputEvent,
     utils::ReflectEnum,
 };
@@ -119,30 +121,24 @@ pub fn styled_space(ui: &mut egui::Ui) {
     ui.add_space(6.0);
 }
 
-fn palette_chooser(ui: &mut egui::Ui) -> &'static Palette {
-    // Id local to the widget
-    // let palette_memory_id = ui.make_persistent_id("active_palette");
-
-    // Global Id
-    let palette_memory_id = egui::Id::new("active_palette");
-
-    let mut active_palette: usize = ui
-        .memory(|memory| memory.data.get_temp(palette_memory_id))
-        .unwrap_or(0);
+/// `active_palette` is an index into `Palette::palettes`
+fn palette_chooser(ui: &mut egui::Ui, active_palette: &mut usize) -> &'static Palette {
     let palettes = Palette::palettes();
+    if *active_palette >= palettes.len() {
+        *active_palette = 0;
+    }
 
     // Show a list of palette buttons instead
     ui.horizontal_wrapped(|ui| {
         for (i_palette, palette) in palettes.iter().enumerate() {
-            let button = styled_button(&palette.name).selected(active_palette == i_palette);
+            let button = styled_button(&palette.name).selected(*active_palette == i_palette);
             if ui.add(button).clicked() {
-                active_palette = i_palette;
+                *active_palette = i_palette;
             }
         }
     });
 
-    ui.memory_mut(|memory| memory.data.insert_temp(palette_memory_id, active_palette));
-    let palette = &palettes[active_palette];
+    let palette = &palettes[*active_palette];
 
     // Link to palette
     ui.hyperlink_to("Link", &palette.link);
@@ -151,18 +147,18 @@ fn palette_chooser(ui: &mut egui::Ui) -> &'static Palette {
 }
 
 /// Return true if the color was changed
-pub fn color_chooser(ui: &mut egui::Ui, color: &mut Rgba8) -> bool {
-    let palette = palette_chooser(ui);
+pub fn color_chooser(ui: &mut egui::Ui, color: &mut Rgba8, active_palette: &mut usize) -> bool {
+    let palette = palette_chooser(ui, active_palette);
 
     // Palette itself
     palette_widget(ui, &palette, color)
 }
 
-pub fn rgb_chooser(ui: &mut egui::Ui, rgb: &mut Rgb8) -> bool {
+pub fn rgb_chooser(ui: &mut egui::Ui, rgb: &mut Rgb8, active_palette: &mut usize) -> bool {
     let palette = if cfg!(feature = "minimal_ui") {
         &Palette::palettes()[0]
     } else {
-        let palette = palette_chooser(ui);
+        let palette = palette_chooser(ui, active_palette);
         styled_space(ui);
         palette
     };
@@ -207,16 +203,17 @@ pub fn system_material_widget(ui: &mut egui::Ui, material: &mut Material) -> boo
         ui.horizontal(|ui| {
             btn(ui, "Placeholder", Material::RULE_PLACEHOLDER);
             btn(ui, "Choice", Material::RULE_CHOICE);
+            btn(ui, "Import", Material::RULE_IMPORT);
         });
     });
 
     color_set
 }
 
-pub fn material_chooser(ui: &mut egui::Ui, material: &mut Material) {
+pub fn material_chooser(ui: &mut egui::Ui, material: &mut Material, active_palette: &mut usize) {
     // Color
     let mut rgb = material.rgb;
-    if rgb_chooser(ui, &mut rgb) {
+    if rgb_chooser(ui, &mut rgb, active_palette) {
         *material = match material.class {
             MaterialClass::Solid => Material::new(rgb, MaterialClass::Solid),
             MaterialClass::Sleeping => Material::new(rgb, MaterialClass::Sleeping),
@@ -294,12 +291,12 @@ pub fn brush_size_chooser(ui: &mut egui::Ui, size: &mut i64) {
     });
 }
 
-pub fn brush_chooser(ui: &mut egui::Ui, brush: &mut Brush) {
+pub fn brush_chooser(ui: &mut egui::Ui, brush: &mut Brush, active_palette: &mut usize) {
     // Brush shape
     brush_size_chooser(ui, &mut brush.size);
     ui.add_space(10.0);
 
-    material_chooser(ui, &mut brush.material);
+    material_chooser(ui, &mut brush.material, active_palette);
 }
 
 struct Prefab {
@@ -444,11 +441,18 @@ impl FileChooser {
             }
         }
 
-        // files ending in ".png" in folder "resources/saves"
-        let extension = OsStr::new("png");
+        // png, project and Aseprite files in folder "resources/saves"
+        let extensions = [
+            OsStr::new("png"),
+            OsStr::new(project::EXTENSION),
+            OsStr::new(aseprite::EXTENSION),
+        ];
         let files: Vec<_> = dir_entries
             .iter()
-            .filter(|path| path.extension() == Some(extension))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extensions.contains(&extension))
+            })
             .collect();
 
         let file_names: Vec<_> = files
@@ -1,5 +1,7 @@
 use crate::{
+    aseprite::{self, AsepriteFile},
     field::RgbaField,
+    image_import::{UnknownAlpha, import_image},
     material::Material,
     material_effects::paint_material_map_effects,
     math::{
@@ -8,12 +10,14 @@ use crate::{
         rgba8::{Rgb8, Rgba8},
     },
     pixmap::MaterialMap,
+    project::{self, Project},
     rule::FillRegion,
     topology::{RegionKey, Topology},
     view::Selection,
 };
 use itertools::Itertools;
 use std::{
+    ffi::OsStr,
     path::Path,
     sync::{Arc, RwLock},
 };
@@ -29,6 +33,13 @@ pub struct World {
 
     /// Where `rgba_field` is not fresh anymore and needs to be recomputed.
     rgba_field_expired_bounds: Rect<i64>,
+
+    /// Where `rgba_field` was already recomputed, for example by `snapshot`, but the change was not
+    /// returned by `update_rgba_field` yet.
+    rgba_field_painted_bounds: Rect<i64>,
+
+    /// Pixels set since the last `take_edits`, None if edits are not recorded.
+    edits: Option<Vec<(Pixel, Material)>>,
 }
 
 impl World {
@@ -39,13 +50,76 @@ impl World {
                 Rgba8::TRANSPARENT,
             ))),
             rgba_field_expired_bounds: material_map.bounding_rect(),
+            rgba_field_painted_bounds: Rect::EMPTY,
             topology: Topology::new(&material_map),
             material_map,
+            edits: None,
+        }
+    }
+
+    /// Copy of the world that does not share `rgba_field` with `self`, used to pass the world
+    /// between threads. The `rgba_field` is painted before copying, changes that were not returned
+    /// by `update_rgba_field` yet are returned by `update_rgba_field` of both worlds.
+    pub fn snapshot(&mut self) -> Self {
+        let expired_bounds = std::mem::replace(&mut self.rgba_field_expired_bounds, Rect::EMPTY);
+        if !expired_bounds.is_empty() {
+            let mut write_rgba_field = self.rgba_field.write().unwrap();
+            paint_material_map_effects(&self.material_map, &mut write_rgba_field);
+            self.rgba_field_painted_bounds = self
+                .rgba_field_painted_bounds
+                .bounds_with_rect(expired_bounds);
+        }
+
+        let rgba_field = self.rgba_field.read().unwrap().clone();
+        Self {
+            material_map: self.material_map.clone(),
+            topology: self.topology.clone(),
+            rgba_field: Arc::new(RwLock::new(rgba_field)),
+            rgba_field_expired_bounds: Rect::EMPTY,
+            rgba_field_painted_bounds: self.rgba_field_painted_bounds,
+            edits: None,
         }
     }
 
+    /// When `self` replaces `previous` on screen, changes of `previous` that were not returned by
+    /// `update_rgba_field` yet also have to be returned by `update_rgba_field` of `self`.
+    pub fn inherit_rgba_changes(&mut self, previous: &World) {
+        self.rgba_field_painted_bounds = self
+            .rgba_field_painted_bounds
+            .bounds_with_rect(previous.rgba_field_painted_bounds)
+            .bounds_with_rect(previous.rgba_field_expired_bounds);
+    }
+
+    /// Start recording the pixels set by edits, see `take_edits`.
+    pub fn record_edits(&mut self) {
+        self.edits.get_or_insert_with(Vec::new);
+    }
+
+    pub fn is_recording_edits(&self) -> bool {
+        self.edits.is_some()
+    }
+
+    /// Stop recording edits, the ones that were not taken yet are dropped.
+    pub fn stop_recording_edits(&mut self) {
+        self.edits = None;
+    }
+
+    /// Pixels set since recording started or since the last call, in the order they were set.
+    pub fn take_edits(&mut self) -> Vec<(Pixel, Material)> {
+        self.edits.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Load a png, a project file, see `Project`, or the visible layers of an Aseprite file
     pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
-        let material_map = MaterialMap::load(path)?;
+        let path = path.as_ref();
+        let material_map = if path.extension() == Some(OsStr::new(project::EXTENSION)) {
+            Project::load(path)?.material_map
+        } else if path.extension() == Some(OsStr::new(aseprite::EXTENSION)) {
+            let rgba_field = AsepriteFile::decode(&std::fs::read(path)?)?.flatten(None)?;
+            import_image(&rgba_field, UnknownAlpha::Reject, None)?.0
+        } else {
+            MaterialMap::load(path)?
+        };
         let world = Self::from_material_map(material_map);
         Ok(world)
     }
@@ -72,7 +146,8 @@ impl World {
         self.rgba_field_expired_bounds = self.rgba_field_expired_bounds.bounds_with_rect(padded);
     }
 
-    /// Recomputes stale areas of rgba_field, so can be expensive
+    /// Recomputes stale areas of rgba_field, so can be expensive. Returns the area of rgba_field
+    /// that changed since the last call.
     pub fn update_rgba_field(&mut self) -> Rect<i64> {
         let expired_bounds = self.rgba_field_expired_bounds;
         if !expired_bounds.is_empty() {
@@ -80,7 +155,8 @@ impl World {
             paint_material_map_effects(&self.material_map, &mut write_rgba_field);
             self.rgba_field_expired_bounds = Rect::EMPTY;
         }
-        expired_bounds
+        let painted_bounds = std::mem::replace(&mut self.rgba_field_painted_bounds, Rect::EMPTY);
+        expired_bounds.bounds_with_rect(painted_bounds)
     }
 
     pub fn fill_region(&mut self, region_key: RegionKey, material: Material) {
@@ -109,19 +185,25 @@ impl World {
         // the material_map. For example when swapping the materials of two touching
         // regions. Also fill regions in the material_map.
         for &fill in &fills {
-            let region = &mut self.topology[fill.region_key];
+            let region = &self.topology[fill.region_key];
 
             // Update material_map.
             let region_area = region.boundary.interior_area();
             for &pixel in &region_area {
                 self.material_map.set(pixel, fill.material);
             }
-
-            // Assign temporary material in topology.
-            region.material = temporary_materials.next().unwrap();
+            if let Some(edits) = &mut self.edits {
+                edits.extend(region_area.iter().map(|&pixel| (pixel, fill.material)));
+            }
 
             let region_bounds = region.bounds();
             self.expire_rgba_rect(region_bounds);
+
+            // Assign temporary material in topology.
+            self.topology.set_region_material_unchecked(
+                fill.region_key,
+                temporary_materials.next().unwrap(),
+            );
         }
 
         // Try to assign material without collapsing neighboring regions
@@ -154,6 +236,9 @@ impl World {
             let previous_material = self.material_map.set(pixel, material);
             if previous_material != Some(material) {
                 changed_pixels.push(pixel);
+                if let Some(edits) = &mut self.edits {
+                    edits.push((pixel, material));
+                }
             }
         }
 
@@ -170,8 +255,30 @@ impl World {
         true
     }
 
+    /// Like `draw` but the pixels are not recorded as edits, for changes that were made to another
+    /// copy of the world.
+    pub fn draw_unrecorded(
+        &mut self,
+        pixel_materials: impl Iterator<Item = (Pixel, Material)>,
+    ) -> bool {
+        let edits = self.edits.take();
+        let changed = self.draw(pixel_materials);
+        self.edits = edits;
+        changed
+    }
+
     /// Blit passed Pixmap to self.material_map but only where material_map is already defined.
     pub fn blit(&mut self, other: &MaterialMap) {
+        if let Some(edits) = &mut self.edits {
+            let rect = self
+                .material_map
+                .bounding_rect()
+                .intersect(other.bounding_rect());
+            edits.extend(
+                rect.iter_indices()
+                    .filter_map(|pixel| Some((pixel, other.get(pixel)?))),
+            );
+        }
         self.material_map.blit(other);
         self.topology
             .update(&self.material_map, other.bounding_rect().iter_indices());
@@ -184,6 +291,12 @@ impl World {
         for pixel in rect.iter_indices() {
             selection.put(pixel, self.material_map.set(pixel, Material::TRANSPARENT));
         }
+        if let Some(edits) = &mut self.edits {
+            edits.extend(
+                rect.iter_indices()
+                    .map(|pixel| (pixel, Material::TRANSPARENT)),
+            );
+        }
 
         self.topology
             .update(&self.material_map, rect.iter_indices());
@@ -201,6 +314,13 @@ impl World {
         for &pixel in &region_area {
             selection.put(pixel, self.material_map.set(pixel, Material::TRANSPARENT));
         }
+        if let Some(edits) = &mut self.edits {
+            edits.extend(
+                region_area
+                    .iter()
+                    .map(|&pixel| (pixel, Material::TRANSPARENT)),
+            );
+        }
 
         self.topology
             .update(&self.material_map, region_area.into_iter());
@@ -244,8 +364,14 @@ impl From<MaterialMap> for World {
 #[cfg(test)]
 mod test {
     use crate::{
-        field::RgbaField, material::Material, pixmap::MaterialMap, rule::FillRegion,
-        topology::Topology, utils::IntoT, world::World,
+        field::RgbaField,
+        material::Material,
+        math::{point::Point, rect::Rect, rgba8::Rgba8},
+        pixmap::MaterialMap,
+        rule::FillRegion,
+        topology::Topology,
+        utils::IntoT,
+        world::World,
     };
 
     fn assert_blit(name: &str) {
@@ -322,6 +448,20 @@ mod test {
         assert_eq!(world.topology, topology);
     }
 
+    /// Images from elsewhere fail to load instead of panicking.
+    #[test]
+    fn load_unknown_alpha() {
+        let path = std::env::temp_dir().join("topolang_load_unknown_alpha.png");
+        let mut rgba_field =
+            RgbaField::filled(Rect::low_size(Point(0, 0), Point(2, 2)), Rgba8::ZERO);
+        rgba_field[(1, 1)] = Rgba8::new(0x12, 0x34, 0x56, 100);
+        rgba_field.save(&path).unwrap();
+
+        let err = World::load(&path).unwrap_err();
+        assert!(err.to_string().contains("unknown alpha"));
+        std::fs::remove_file(path).unwrap();
+    }
+
     #[test]
     fn fill_region_a() {
         test_fill_region("a", vec![(Material::RED, Material::GREEN)]);